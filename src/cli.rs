@@ -1,16 +1,15 @@
 use anyhow::anyhow;
-use std::fs::File;
-use std::io::Write;
 use std::path::Path;
 
 use clap::Parser;
 use git2::Repository;
-use serde_json::to_string_pretty;
 use thiserror::Error;
 use tracing::{error, info, trace, Level};
 
-use crate::config::Config;
+use crate::config::{AlertLevel, Config};
+use crate::engine::{self, RuleError};
 use crate::git;
+use crate::report::Report;
 use crate::transform::init_registry;
 use crate::utils;
 use anyhow::Result;
@@ -25,14 +24,29 @@ use anyhow::Result;
 
     In case of local usage, we have a few options
     - specify url & analyze impact of specified branches/commits
-    from_branch fallbacks to default after clone
-    if no branch & commit specified, fails as there is nothing to compare
+    from_branch fallbacks to default branch of the repository after clone
     - dont specify url, specify path
     creates repository struct from local path
-    from_branch fallbacks to default after opening
-    if branches specified & local changes detected, optionally includes those
-    if no branch & commit specified, tries (not yet) to analyze local changes
-    if no local changes fails as there is nothing to compare
+
+    Changes to analyze are picked in the following order:
+    1. --of-commit: changes introduced by a single commit
+    2. --range: changes in an arbitrary A..B or A...B range
+    3. --from-branch: changes made on --from-branch, compared against --to-branch.
+    If url is specified, defaults to the default branch of the repository.
+    With --merge-base, only changes made on --from-branch since it
+    diverged from --to-branch are analyzed, same as in a pull request
+    4. --staged: changes staged in the index
+    5. otherwise, all uncommitted changes in the working tree
+    if there are no local changes, fails as there is nothing to compare
+
+    Exit codes:
+    0 - analysis finished, no findings at or above --fail-on level
+    1 - internal error
+    2 - invalid arguments
+    3 - invalid or missing config, or a rule which can not be evaluated
+    4 - git operation failed
+    10, 11, 12 - findings at or above --fail-on level were detected,
+    highest of which is Info, Warn or Severe respectively
 "#
 )]
 struct Args {
@@ -44,6 +58,8 @@ struct Args {
     config: String,
 
     /// From what branch changes should be compared.
+    ///
+    /// If url is specified, defaults to the default branch of the repository.
     #[arg(long)]
     from_branch: Option<String>,
 
@@ -58,6 +74,32 @@ struct Args {
     #[arg(long)]
     of_commit: Option<String>,
 
+    /// Revision range of which changes should be analyzed, e.g. `main..feature`
+    /// or `main...feature`. Takes precedence over branch changes.
+    #[arg(long)]
+    range: Option<String>,
+
+    /// Compare `from_branch` against its merge-base with `to_branch`,
+    /// instead of comparing the branches directly.
+    #[arg(long)]
+    merge_base: bool,
+
+    /// Analyze changes staged in the index, instead of all uncommitted changes.
+    /// Used only if neither commit, range, nor branch is specified.
+    #[arg(long)]
+    staged: bool,
+
+    /// Path, to which JSON report of the run is written.
+    #[arg(short, long, default_value_t = String::from("impactifier-report.json"))]
+    output: String,
+
+    /// Fail, if any finding has alert level at least as high as this one.
+    /// Takes precedence over `fail_on` from the config file.
+    ///
+    /// If unspecified in both, findings never make the run fail.
+    #[arg(long, value_enum)]
+    fail_on: Option<AlertLevel>,
+
     /// Fetch last changes before impact analysis
     #[arg(long)]
     fetch: bool,
@@ -72,6 +114,7 @@ struct Args {
     #[arg(long, default_value_t = 2)]
     tracing_level: u8,
 
+    /// Name of the remote, whose branches take precedence when resolving refs.
     #[arg(long, default_value_t=String::from("origin"))]
     origin: String,
 
@@ -89,6 +132,28 @@ pub fn run() -> Result<(), CliError> {
     let args = Args::parse();
     setup_logging(args.tracing_level);
 
+    let mut report = Report::new();
+    let result = analyze(&args, &mut report);
+
+    report.finish(result.as_ref().err().map(CliError::describe));
+    if let Err(err) = report.save(Path::new(&args.output)) {
+        error!("Failed to save report to {}", args.output);
+        let output_err = CliError::OutputFailure {
+            path: args.output.clone(),
+            err,
+        };
+        // Error which made the run fail is more relevant than the one of saving the report
+        return result.and(Err(output_err));
+    }
+
+    let fail_on = result?;
+    check_fail_on(&report, fail_on)
+}
+
+/// Runs the analysis, and fills the report with its results.
+///
+/// Returns alert level, at which findings make the run fail.
+fn analyze(args: &Args, report: &mut Report) -> Result<Option<AlertLevel>, CliError> {
     let cfg = match load_config(Path::new(&args.config)) {
         Ok(config) => config,
         Err(e) => {
@@ -98,7 +163,22 @@ pub fn run() -> Result<(), CliError> {
     };
     trace!("Initial config load succeeded");
 
-    init_registry(cfg.custom_transform_scripts());
+    let custom_scripts = match cfg.custom_transform_scripts() {
+        Ok(scripts) => scripts,
+        Err(err) => {
+            error!("Failed to load custom transform scripts");
+            return Err(CliError::InvalidConfigPath {
+                err: Some(err.into()),
+            });
+        }
+    };
+
+    if let Err(err) = init_registry(custom_scripts, &cfg.options.script_limits) {
+        error!("Failed to initialize transform functions");
+        return Err(CliError::InvalidConfigPath {
+            err: Some(err.into()),
+        });
+    }
     trace!("Transform functions initialized successfully");
 
     let clone_into = match cfg.options.clone_into.as_deref() {
@@ -106,15 +186,38 @@ pub fn run() -> Result<(), CliError> {
         None => Path::new("cloned_repository"),
     };
 
-    let credentials = utils::get_git_credentials(args.ssh_key_path, args.username, args.https_pat);
+    let credentials = utils::get_git_credentials(
+        args.ssh_key_path.clone(),
+        args.username.clone(),
+        args.https_pat.clone(),
+    );
 
-    let repository_retrieval_result = match cfg.repository.url {
-        Some(url) => {
-            if let Err(err) = utils::prepare_directory(clone_into) {
-                return Err(CliError::Unknown { err: Some(err) });
+    // Freshly cloned repository has no local changes, so its default branch is analyzed
+    let default_branch = match &cfg.repository.url {
+        Some(url) if needs_default_branch(args) => match git::default_branch(url, &credentials) {
+            Ok(branch) => {
+                info!("Analyzing default branch {} of the repository", branch);
+                Some(branch)
             }
-            git::clone_repo(&credentials, &url, clone_into).map_err(|err| anyhow!(err))
-        }
+            Err(err) => {
+                error!("Failed to retrieve default branch of the repository");
+                return Err(CliError::GitFailure { err: Some(err) });
+            }
+        },
+        _ => None,
+    };
+
+    let diff_options = diff_options(args, default_branch.as_deref());
+
+    let repository_retrieval_result = match cfg.repository.url {
+        Some(url) => git::clone_with_history(
+            &credentials,
+            &url,
+            clone_into,
+            &args.origin,
+            &diff_options,
+            cfg.options.max_fetch_depth,
+        ),
         None => match &cfg.repository.path {
             Some(path) => try_retrieve_repo_from_path(path),
             None => {
@@ -127,50 +230,147 @@ pub fn run() -> Result<(), CliError> {
 
     let repository = match repository_retrieval_result {
         Ok(repository) => repository,
-        Err(err) => return Err(CliError::Unknown { err: Some(err) }),
+        Err(err) => return Err(CliError::GitFailure { err: Some(err) }),
     };
     trace!("Successfully retrieved repository");
 
-    if let Err(fetch_err) = git::fetch_remote(&repository, &args.origin, &credentials) {
-        error!("Failed to fetch remote");
-        return Err(CliError::Unknown {
-            err: Some(fetch_err),
-        });
+    if args.fetch {
+        if let Err(fetch_err) = git::fetch_remote(&repository, &args.origin, &credentials) {
+            error!("Failed to fetch remote");
+            return Err(CliError::GitFailure {
+                err: Some(fetch_err),
+            });
+        }
+        trace!("Successfully fetched remote");
     }
-    trace!("Successfully fetched remote");
 
-    // TODO: Support other DiffOptions
-    //
-    // Current one is temporary, just for testing purposes
-    let _diff = match git::extract_difference(
+    if let Err(err) = git::deepen_history(
         &repository,
-        &git::DiffOptions::Branches {
-            from: &args.from_branch.unwrap(),
-            to: &args.to_branch,
-        },
+        &args.origin,
+        &diff_options,
+        &credentials,
+        cfg.options.max_fetch_depth,
+    ) {
+        error!("Failed to fetch history required for comparison");
+        return Err(CliError::GitFailure { err: Some(err) });
+    }
+
+    let diff = match git::extract_difference(
+        &repository,
+        &args.origin,
+        &diff_options,
+        &cfg.options.renames,
     ) {
         Ok(diff) => diff,
         Err(err) => {
             error!("Failed to extract difference");
-            // Temporary, for testing purposes
-            save_run_result(false);
-            return Err(CliError::Unknown { err: Some(err) });
+            return Err(CliError::GitFailure { err: Some(err) });
         }
     };
     trace!("Successfuly extracted difference");
+    report.set_comparison(&diff);
 
-    // Temporary, for testing purposes
-    save_run_result(true);
+    if diff.is_empty() {
+        if let git::DiffOptions::WorkingTree = diff_options {
+            error!("No local changes detected");
+            return Err(CliError::InvalidArgs {
+                err: Some(anyhow!(
+                    "Nothing to compare. Specify commit, range or branch, or make local changes"
+                )),
+            });
+        }
+    }
 
-    Ok(())
+    let findings = match engine::run_rules(&repository, &cfg.rules, &diff) {
+        Ok(findings) => findings,
+        Err(err) => {
+            error!("Failed to run rules");
+            return Err(rule_failure(err));
+        }
+    };
+    info!("Analysis finished with {} finding(s)", findings.len());
+    report.set_findings(&cfg.rules, findings);
+
+    Ok(args.fail_on.or(cfg.options.fail_on))
 }
 
-fn save_run_result(is_success: bool) {
-    let text = if is_success { "SUCCESS" } else { "FAILURE" };
-    let serialized_diff = to_string_pretty(text).unwrap();
+/// Maps failure of a rule to the error of its kind, so the run exits with a matching code.
+fn rule_failure(err: RuleError) -> CliError {
+    match err {
+        RuleError::TransformFailure { .. }
+        | RuleError::InvalidMatcherPattern { .. }
+        | RuleError::ScriptLimitExceeded { .. } => CliError::InvalidRule {
+            err: Some(err.into()),
+        },
+        RuleError::BareRepository
+        | RuleError::ContentReadFailure { .. }
+        | RuleError::RevisionSearchFailure { .. }
+        | RuleError::CommitReadFailure { .. } => CliError::GitFailure {
+            err: Some(err.into()),
+        },
+        RuleError::SearchFailure { .. } => CliError::Unknown {
+            err: Some(err.into()),
+        },
+    }
+}
+
+fn check_fail_on(report: &Report, fail_on: Option<AlertLevel>) -> Result<(), CliError> {
+    let Some(fail_on) = fail_on else {
+        return Ok(());
+    };
+
+    let failing: Vec<AlertLevel> = report
+        .findings()
+        .map(|finding| finding.alert_level)
+        .filter(|level| *level >= fail_on)
+        .collect();
 
-    let mut file = File::create("./diff.json").unwrap();
-    file.write_all(serialized_diff.as_bytes()).unwrap();
+    match failing.iter().max() {
+        Some(level) => {
+            error!(
+                "{} finding(s) at or above {:?} alert level detected",
+                failing.len(),
+                fail_on
+            );
+            Err(CliError::ImpactDetected {
+                level: *level,
+                count: failing.len(),
+            })
+        }
+        None => Ok(()),
+    }
+}
+
+/// Whether none of commit, range, nor branch to analyze is specified.
+fn needs_default_branch(args: &Args) -> bool {
+    args.of_commit.is_none() && args.range.is_none() && args.from_branch.is_none()
+}
+
+/// Picks what changes should be analyzed, in order described in `Args` long_about.
+///
+/// `default_branch` is used in place of `from_branch`, if it is not specified.
+fn diff_options<'a>(args: &'a Args, default_branch: Option<&'a str>) -> git::DiffOptions<'a> {
+    if let Some(id) = &args.of_commit {
+        return git::DiffOptions::Commit { id };
+    }
+
+    if let Some(spec) = &args.range {
+        return git::DiffOptions::Range { spec };
+    }
+
+    // Changes are made on --from-branch, and are meant to be merged into --to-branch
+    if let Some(head) = args.from_branch.as_deref().or(default_branch) {
+        let base = &args.to_branch;
+        return match args.merge_base {
+            true => git::DiffOptions::MergeBase { head, base },
+            false => git::DiffOptions::Branches { head, base },
+        };
+    }
+
+    match args.staged {
+        true => git::DiffOptions::Staged,
+        false => git::DiffOptions::WorkingTree,
+    }
 }
 
 fn try_retrieve_repo_from_path(path: &Path) -> Result<Repository> {
@@ -222,6 +422,144 @@ pub enum CliError {
     InvalidArgs { err: Option<anyhow::Error> },
     #[error("Config can not be retrieved")]
     InvalidConfigPath { err: Option<anyhow::Error> },
+    #[error("Rule can not be evaluated: {:?}", err)]
+    InvalidRule { err: Option<anyhow::Error> },
     #[error("Unknown error: {:?}", err)]
     Unknown { err: Option<anyhow::Error> },
+    #[error("Git operation failed: {:?}", err)]
+    GitFailure { err: Option<anyhow::Error> },
+    #[error("Failed to write output to {}. Error: {}", path, err)]
+    OutputFailure { path: String, err: std::io::Error },
+    #[error("Detected {} finding(s), highest alert level: {:?}", count, level)]
+    ImpactDetected { level: AlertLevel, count: usize },
+}
+
+impl CliError {
+    /// Short, human readable description of the error, without a backtrace.
+    pub fn describe(&self) -> String {
+        let cause = match self {
+            CliError::InvalidArgs { err }
+            | CliError::InvalidConfigPath { err }
+            | CliError::InvalidRule { err }
+            | CliError::GitFailure { err }
+            | CliError::Unknown { err } => err.as_ref(),
+            CliError::OutputFailure { .. } | CliError::ImpactDetected { .. } => None,
+        };
+
+        match cause {
+            Some(cause) => format!("{:#}", cause),
+            None => self.to_string(),
+        }
+    }
+
+    /// Exit code of the process, as listed in `Args` long_about.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            CliError::Unknown { .. } | CliError::OutputFailure { .. } => 1,
+            CliError::InvalidArgs { .. } => 2,
+            CliError::InvalidConfigPath { .. } | CliError::InvalidRule { .. } => 3,
+            CliError::GitFailure { .. } => 4,
+            CliError::ImpactDetected { level, .. } => match level {
+                AlertLevel::Info => 10,
+                AlertLevel::Warn => 11,
+                AlertLevel::Severe => 12,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git::DiffOptions;
+
+    fn parse(args: &[&str]) -> Args {
+        Args::try_parse_from(std::iter::once("impactifier").chain(args.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn diff_options_picks_commit_first() {
+        let args = parse(&[
+            "--of-commit",
+            "abc",
+            "--range",
+            "a..b",
+            "--from-branch",
+            "f",
+        ]);
+        assert!(matches!(
+            diff_options(&args, None),
+            DiffOptions::Commit { id: "abc" }
+        ));
+    }
+
+    #[test]
+    fn diff_options_picks_range_before_branches() {
+        let args = parse(&[
+            "--range",
+            "main...feature",
+            "--from-branch",
+            "f",
+            "--staged",
+        ]);
+        assert!(matches!(
+            diff_options(&args, None),
+            DiffOptions::Range {
+                spec: "main...feature"
+            }
+        ));
+    }
+
+    #[test]
+    fn diff_options_compares_from_branch_against_to_branch() {
+        let args = parse(&["--from-branch", "feature", "--staged"]);
+        assert!(!needs_default_branch(&args));
+        assert!(matches!(
+            diff_options(&args, Some("trunk")),
+            DiffOptions::Branches {
+                head: "feature",
+                base: "main"
+            }
+        ));
+
+        let args = parse(&[
+            "--from-branch",
+            "feature",
+            "--to-branch",
+            "dev",
+            "--merge-base",
+        ]);
+        assert!(matches!(
+            diff_options(&args, None),
+            DiffOptions::MergeBase {
+                head: "feature",
+                base: "dev"
+            }
+        ));
+    }
+
+    #[test]
+    fn diff_options_falls_back_to_default_branch() {
+        let args = parse(&["--to-branch", "release", "--merge-base"]);
+        assert!(needs_default_branch(&args));
+        assert!(matches!(
+            diff_options(&args, Some("trunk")),
+            DiffOptions::MergeBase {
+                head: "trunk",
+                base: "release"
+            }
+        ));
+    }
+
+    #[test]
+    fn diff_options_picks_local_changes_without_branch() {
+        assert!(matches!(
+            diff_options(&parse(&["--staged"]), None),
+            DiffOptions::Staged
+        ));
+        assert!(matches!(
+            diff_options(&parse(&[]), None),
+            DiffOptions::WorkingTree
+        ));
+    }
 }
@@ -1,24 +1,53 @@
 use anyhow::Result;
-use serde::{Deserialize, Deserializer};
+use globset::{GlobBuilder, GlobMatcher, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize};
 use std::cmp;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 use tracing::debug;
 use tracing::error;
 use url::Url;
 
+use crate::git::FileStatus;
+use crate::transform::is_standard_transform;
+
 #[derive(Error, Debug)]
 pub enum ConfigError {
     #[error("Failed to read config from path: {}. Error:{}", path, msg)]
     ReadFailure { path: String, msg: String },
+    #[error("Failed to read script from path: {}. Error:{}", path, msg)]
+    ScriptReadFailure { path: String, msg: String },
+    #[error("Custom step {} is invalid. Error:{}", name, msg)]
+    InvalidCustomStep { name: String, msg: String },
+    #[error("Transforms defined at the top level of the config must have a name")]
+    UnnamedTransform,
+    #[error("Transform {} is defined more than once", name)]
+    DuplicateTransform { name: String },
+    #[error("Transform {} referenced by {} is not defined", name, referenced_by)]
+    UnknownTransform { name: String, referenced_by: String },
+    #[error("Transforms reference each other in a cycle: {}", cycle)]
+    TransformCycle { cycle: String },
+    #[error("Rule {} is defined more than once", name)]
+    DuplicateRule { name: String },
+    #[error("Trigger of rule {} is invalid. Error:{}", rule, msg)]
+    InvalidTrigger { rule: String, msg: String },
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub repository: RepositoryConfig,
     pub options: OptionsConfig,
+    /// Named transforms, which can be referenced by rules and other transforms.
+    #[serde(default)]
+    pub transforms: Vec<Transform>,
     pub rules: Vec<Rule>,
+    /// Directory of the config file, to which paths in the config are relative.
+    #[serde(skip)]
+    pub base_dir: PathBuf,
 }
 
 #[derive(Debug, Deserialize)]
@@ -32,23 +61,608 @@ pub struct RepositoryConfig {
 #[derive(Debug, Deserialize)]
 pub struct OptionsConfig {
     pub clone_into: Option<Box<Path>>,
+    #[serde(default)]
+    pub renames: RenamesConfig,
+    /// Max depth to which history of a shallow repository is deepened,
+    /// while looking for a common ancestor of compared refs.
+    /// If unspecified, repository is unshallowed when needed.
+    pub max_fetch_depth: Option<u32>,
+    /// Run fails, if any finding has alert level at least as high as this one.
+    /// Overridden by `--fail-on`.
+    pub fail_on: Option<AlertLevel>,
+    #[serde(default)]
+    pub script_limits: ScriptLimitsConfig,
+    /// Every `.rhai` file in this directory is registered as a custom transform,
+    /// named after the file, e.g. `toRestPath.rhai` as `toRestPath`.
+    pub scripts_dir: Option<Box<Path>>,
+}
+
+/// Controls detection of renamed and copied files in the diff.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RenamesConfig {
+    pub enabled: bool,
+    pub copies: bool,
+    /// Minimal similarity, in percents, for files to be considered renamed or copied.
+    #[serde(deserialize_with = "deserialize_percentage")]
+    pub similarity_threshold: u16,
+}
+
+impl Default for RenamesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            copies: true,
+            similarity_threshold: 50,
+        }
+    }
 }
 
+/// Limits applied to custom transform scripts, so a faulty one can not hang the run.
+/// Limit set to 0 is disabled.
 #[derive(Debug, Deserialize)]
-pub struct TransformStep {
+#[serde(default)]
+pub struct ScriptLimitsConfig {
+    pub max_operations: u64,
+    pub max_call_depth: usize,
+    pub max_string_size: usize,
+    pub max_array_size: usize,
+    pub max_map_size: usize,
+    /// Wall-clock time, in milliseconds, which a single call of the script may take.
+    pub timeout_ms: u64,
+}
+
+impl Default for ScriptLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_operations: 1_000_000,
+            max_call_depth: 64,
+            max_string_size: 1024 * 1024,
+            max_array_size: 10_000,
+            max_map_size: 10_000,
+            timeout_ms: 5_000,
+        }
+    }
+}
+
+/// Single step of a transform. Either a transform run on the current value,
+/// or a branch, which picks steps to run depending on a condition.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "RawTransformStep")]
+pub enum TransformStep {
+    Run(RunStep),
+    Branch(BranchStep),
+}
+
+#[derive(Debug, Clone)]
+pub struct RunStep {
     pub name: String,
-    #[serde(default)]
     pub args: Option<serde_yaml::Value>,
+    /// Step is skipped, unless the condition holds.
+    pub when: Option<StepCondition>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone)]
+pub struct BranchStep {
+    pub condition: StepCondition,
+    pub then: Vec<TransformStep>,
+    pub otherwise: Vec<TransformStep>,
+}
+
+/// Condition on the context in which a step runs. Holds, if all specified checks pass.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StepCondition {
+    /// Glob, which path of the changed file must match, e.g. `api/**/*.go`.
+    #[serde(deserialize_with = "deserialize_glob", default)]
+    pub path: Option<GlobMatcher>,
+    /// Extension of the changed file, e.g. `ts`.
+    pub extension: Option<String>,
+    pub class_name: Option<String>,
+    /// Regex, which the current value must match.
+    #[serde(deserialize_with = "deserialize_regex", default)]
+    pub matches: Option<Regex>,
+}
+
+/// Step as written in the config, validated when converted into `TransformStep`.
+#[derive(Deserialize)]
+struct RawTransformStep {
+    name: Option<String>,
+    #[serde(default)]
+    args: Option<serde_yaml::Value>,
+    when: Option<StepCondition>,
+    #[serde(rename = "if")]
+    condition: Option<StepCondition>,
+    #[serde(default)]
+    then: Vec<TransformStep>,
+    #[serde(default, rename = "else")]
+    otherwise: Vec<TransformStep>,
+}
+
+impl TryFrom<RawTransformStep> for TransformStep {
+    type Error = String;
+
+    fn try_from(raw: RawTransformStep) -> Result<Self, Self::Error> {
+        match (raw.name, raw.condition) {
+            (Some(name), None) => {
+                if !raw.then.is_empty() || !raw.otherwise.is_empty() {
+                    return Err(format!(
+                        "step {} can not have `then` or `else` without `if`",
+                        name
+                    ));
+                }
+                Ok(TransformStep::Run(RunStep {
+                    name,
+                    args: raw.args,
+                    when: raw.when,
+                }))
+            }
+            (None, Some(condition)) => {
+                if raw.args.is_some() || raw.when.is_some() {
+                    return Err("step with `if` can not have `args` or `when`".to_string());
+                }
+                Ok(TransformStep::Branch(BranchStep {
+                    condition,
+                    then: raw.then,
+                    otherwise: raw.otherwise,
+                }))
+            }
+            (Some(name), Some(_)) => {
+                Err(format!("step {} can not have both `name` and `if`", name))
+            }
+            (None, None) => Err("step must have either `name` or `if`".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
 pub struct Transform {
-    // pub name: String,
+    /// Required for transforms defined at the top level of the config,
+    /// which are referenced by it. In a rule, it is only a label.
+    pub name: Option<String>,
+    /// Name of the transform, whose steps are run before the ones of this transform.
+    pub extends: Option<String>,
     #[serde(default)]
     pub steps: Vec<TransformStep>,
 }
 
+impl Transform {
+    /// Steps, which run a transform, including the ones nested in branches.
+    pub fn run_steps(&self) -> Vec<&RunStep> {
+        let mut run_steps = Vec::new();
+        collect_run_steps(&self.steps, &mut run_steps);
+        run_steps
+    }
+}
+
+fn collect_run_steps<'a>(steps: &'a [TransformStep], run_steps: &mut Vec<&'a RunStep>) {
+    for step in steps {
+        match step {
+            TransformStep::Run(step) => run_steps.push(step),
+            TransformStep::Branch(branch) => {
+                collect_run_steps(&branch.then, run_steps);
+                collect_run_steps(&branch.otherwise, run_steps);
+            }
+        }
+    }
+}
+
+/// Trigger of a rule: either a single set of conditions, or a group of other triggers.
+///
+/// Triggers are evaluated for each changed file, or once for the whole analysed range,
+/// if none of them has file conditions.
 #[derive(Debug, Deserialize)]
+#[serde(try_from = "RawTrigger")]
+pub enum TriggerNode {
+    /// Fires if all of the triggers fire. Evaluation stops at the first one which does not.
+    All(Vec<TriggerNode>),
+    /// Fires if any of the triggers fires. Evaluation stops at the first one which does.
+    Any(Vec<TriggerNode>),
+    /// Fires if the trigger does not.
+    Not(Box<TriggerNode>),
+    Leaf(Box<Trigger>),
+}
+
+impl TriggerNode {
+    /// Tells whether none of the triggers has file conditions, so it fires for commits only.
+    pub fn is_commit_only(&self) -> bool {
+        match self {
+            TriggerNode::All(triggers) | TriggerNode::Any(triggers) => {
+                triggers.iter().all(TriggerNode::is_commit_only)
+            }
+            TriggerNode::Not(trigger) => trigger.is_commit_only(),
+            TriggerNode::Leaf(trigger) => {
+                trigger.commit.is_some() && !trigger.has_file_conditions()
+            }
+        }
+    }
+
+    /// Tells whether any of the triggers is negated.
+    fn has_negation(&self) -> bool {
+        match self {
+            TriggerNode::All(triggers) | TriggerNode::Any(triggers) => {
+                triggers.iter().any(TriggerNode::has_negation)
+            }
+            TriggerNode::Not(_) => true,
+            TriggerNode::Leaf(_) => false,
+        }
+    }
+
+    /// Tells whether both triggers have the same structure and conditions.
+    fn is_same(&self, other: &TriggerNode) -> bool {
+        match (self, other) {
+            (TriggerNode::All(a), TriggerNode::All(b))
+            | (TriggerNode::Any(a), TriggerNode::Any(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.is_same(b))
+            }
+            (TriggerNode::Not(a), TriggerNode::Not(b)) => a.is_same(b),
+            (TriggerNode::Leaf(a), TriggerNode::Leaf(b)) => a.has_same_conditions(b),
+            _ => false,
+        }
+    }
+
+    /// Rejects triggers, which can never fire.
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            TriggerNode::All(triggers) if triggers.is_empty() => {
+                Err("`all` must contain at least one trigger".to_string())
+            }
+            TriggerNode::Any(triggers) if triggers.is_empty() => {
+                Err("`any` must contain at least one trigger".to_string())
+            }
+            TriggerNode::All(triggers) => {
+                let negates_other = |trigger: &TriggerNode| match trigger {
+                    TriggerNode::Not(negated) => {
+                        triggers.iter().any(|other| other.is_same(negated))
+                    }
+                    _ => false,
+                };
+                if triggers.iter().any(negates_other) {
+                    return Err("`all` contains a trigger along with its negation".to_string());
+                }
+
+                // Conditions of files are checked against the same file, so they can contradict
+                let leaves: Vec<&Trigger> = triggers
+                    .iter()
+                    .filter_map(|trigger| match trigger {
+                        TriggerNode::Leaf(trigger) => Some(trigger.as_ref()),
+                        _ => None,
+                    })
+                    .collect();
+
+                let statuses = leaves
+                    .iter()
+                    .filter(|trigger| !trigger.status.is_empty())
+                    .map(|trigger| trigger.status.clone());
+                if !have_common_value(statuses) {
+                    return Err(
+                        "`all` contains triggers with `status`, which no file can match at once"
+                            .to_string(),
+                    );
+                }
+
+                let extensions = leaves
+                    .iter()
+                    .filter_map(|trigger| trigger.allowed_extensions());
+                if !have_common_value(extensions) {
+                    return Err(
+                        "`all` contains triggers with `extensions` or `languages`, which no file can match at once"
+                            .to_string(),
+                    );
+                }
+
+                Ok(())
+            }
+            TriggerNode::Any(_) => Ok(()),
+            TriggerNode::Not(trigger) => match trigger.as_ref() {
+                TriggerNode::Leaf(trigger)
+                    if !trigger.has_file_conditions() && trigger.commit.is_none() =>
+                {
+                    Err("`not` of a trigger without conditions can never fire".to_string())
+                }
+                _ => Ok(()),
+            },
+            TriggerNode::Leaf(trigger) => match &trigger.commit {
+                Some(CommitTrigger {
+                    min_files_changed: Some(min),
+                    max_files_changed: Some(max),
+                    ..
+                }) if min > max => Err(format!(
+                    "`min_files_changed` {} is greater than `max_files_changed` {}",
+                    min, max
+                )),
+                _ => Ok(()),
+            },
+        }
+    }
+}
+
+/// Trigger as written in the config, validated when converted into `TriggerNode`.
+#[derive(Deserialize)]
+struct RawTrigger {
+    all: Option<Vec<TriggerNode>>,
+    any: Option<Vec<TriggerNode>>,
+    not: Option<Box<TriggerNode>>,
+    #[serde(flatten)]
+    conditions: Trigger,
+}
+
+/// Tells whether any value is allowed by every one of `conditions`.
+fn have_common_value<T: PartialEq>(conditions: impl Iterator<Item = Vec<T>>) -> bool {
+    let mut allowed: Option<Vec<T>> = None;
+    for condition in conditions {
+        let narrowed: Vec<T> = match allowed {
+            Some(allowed) => allowed
+                .into_iter()
+                .filter(|value| condition.contains(value))
+                .collect(),
+            None => condition,
+        };
+        if narrowed.is_empty() {
+            return false;
+        }
+        allowed = Some(narrowed);
+    }
+    true
+}
+
+impl TryFrom<RawTrigger> for TriggerNode {
+    type Error = String;
+
+    fn try_from(raw: RawTrigger) -> Result<Self, Self::Error> {
+        let has_conditions =
+            raw.conditions.has_file_conditions() || raw.conditions.commit.is_some();
+        let node = match (raw.all, raw.any, raw.not) {
+            (None, None, None) => TriggerNode::Leaf(Box::new(raw.conditions)),
+            _ if has_conditions => {
+                return Err(
+                    "trigger with `all`, `any` or `not` can not have other conditions".to_string(),
+                )
+            }
+            (Some(triggers), None, None) => TriggerNode::All(triggers),
+            (None, Some(triggers), None) => TriggerNode::Any(triggers),
+            (None, None, Some(trigger)) => TriggerNode::Not(trigger),
+            _ => return Err("trigger can have only one of `all`, `any` or `not`".to_string()),
+        };
+        node.validate()?;
+
+        Ok(node)
+    }
+}
+
+/// Decides which changes make the rule fire. All specified conditions must hold.
+///
+/// Capture groups of `pattern` are the input of the rule's transform.
+#[derive(Debug, Deserialize)]
+pub struct Trigger {
+    /// Only files under this path are considered. Defaults to the whole repository.
+    pub path: Option<Box<Path>>,
+    /// Gitignore-style globs, e.g. `api/**/*.go` or `!*_test.go`.
+    /// If specified, only files matching them are considered.
+    #[serde(deserialize_with = "deserialize_globs", default)]
+    pub include: Option<Gitignore>,
+    /// Gitignore-style globs of files, which are never considered.
+    #[serde(deserialize_with = "deserialize_globs", default)]
+    pub exclude: Option<Gitignore>,
+    /// If specified, only files changed in one of these ways are considered.
+    #[serde(default)]
+    pub status: Vec<FileStatus>,
+    /// If specified, only files with one of these extensions are considered, e.g. `go`.
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    /// If specified, only files written in one of these languages are considered.
+    #[serde(default)]
+    pub languages: Vec<Language>,
+    #[serde(deserialize_with = "deserialize_regex", default)]
+    pub pattern: Option<Regex>,
+    /// Parts of the changed files, which `pattern` is searched in.
+    /// Defaults to the added and removed lines.
+    #[serde(default = "default_sides")]
+    pub sides: Vec<DiffSide>,
+    /// How similar, from 0 to 1, must be a name which disappeared from the file to one which
+    /// appeared in it, to report them as renamed rather than removed and added.
+    #[serde(
+        deserialize_with = "deserialize_ratio",
+        default = "default_rename_similarity"
+    )]
+    pub rename_similarity: f64,
+    /// Conditions on commits of the analysed range.
+    pub commit: Option<CommitTrigger>,
+}
+
+impl Trigger {
+    /// Tells whether the trigger narrows down changed files, or searches their content.
+    /// Trigger with only commit conditions fires for commits rather than files.
+    pub fn has_file_conditions(&self) -> bool {
+        self.path.is_some()
+            || self.include.is_some()
+            || self.exclude.is_some()
+            || !self.status.is_empty()
+            || !self.extensions.is_empty()
+            || !self.languages.is_empty()
+            || self.pattern.is_some()
+    }
+
+    /// Extensions of files, which the trigger considers. `None` if it considers any.
+    fn allowed_extensions(&self) -> Option<Vec<&str>> {
+        if self.extensions.is_empty() && self.languages.is_empty() {
+            return None;
+        }
+        let extensions = self
+            .extensions
+            .iter()
+            .map(|extension| extension.trim_start_matches('.'));
+        let language_extensions = self
+            .languages
+            .iter()
+            .flat_map(|language| language.extensions().iter().copied());
+
+        Some(extensions.chain(language_extensions).collect())
+    }
+
+    /// Tells whether both triggers have the same conditions. Globs can not be compared
+    /// once built, so triggers with any of them are never considered the same.
+    fn has_same_conditions(&self, other: &Trigger) -> bool {
+        let same_regex = |a: &Option<Regex>, b: &Option<Regex>| {
+            a.as_ref().map(Regex::as_str) == b.as_ref().map(Regex::as_str)
+        };
+        let same_commit = match (&self.commit, &other.commit) {
+            (None, None) => true,
+            (Some(a), Some(b)) => {
+                same_regex(&a.message, &b.message)
+                    && a.types == b.types
+                    && a.scopes == b.scopes
+                    && a.breaking == b.breaking
+                    && a.trailers.len() == b.trailers.len()
+                    && a.trailers
+                        .iter()
+                        .zip(&b.trailers)
+                        .all(|(a, b)| a.name == b.name && same_regex(&a.value, &b.value))
+                    && [&a.author, &a.committer, &b.author, &b.committer]
+                        .iter()
+                        .all(|globs| globs.is_none())
+                    && a.min_files_changed == b.min_files_changed
+                    && a.max_files_changed == b.max_files_changed
+            }
+            _ => false,
+        };
+
+        same_commit
+            && [&self.include, &self.exclude, &other.include, &other.exclude]
+                .iter()
+                .all(|globs| globs.is_none())
+            && self.path == other.path
+            && self.status == other.status
+            && self.extensions == other.extensions
+            && self.languages == other.languages
+            && same_regex(&self.pattern, &other.pattern)
+            && self.sides == other.sides
+            && self.rename_similarity == other.rename_similarity
+    }
+}
+
+/// Conditions on a single commit of the analysed range. All specified conditions must hold.
+///
+/// Capture groups of `message` are the input of the rule's transform,
+/// if trigger fires for the commit itself.
+#[derive(Debug, Deserialize)]
+pub struct CommitTrigger {
+    /// Regex searched in the whole commit message.
+    #[serde(deserialize_with = "deserialize_regex", default)]
+    pub message: Option<Regex>,
+    /// Conventional commit types, e.g. `feat` or `fix`.
+    #[serde(default, rename = "type")]
+    pub types: Vec<String>,
+    /// Conventional commit scopes, e.g. `api`.
+    #[serde(default, rename = "scope")]
+    pub scopes: Vec<String>,
+    /// Whether commit is marked as breaking, with `!` or `BREAKING CHANGE` trailer.
+    pub breaking: Option<bool>,
+    /// Trailers, which the commit message must contain.
+    #[serde(default)]
+    pub trailers: Vec<TrailerCondition>,
+    /// Globs of author emails, e.g. `*@example.com`.
+    #[serde(deserialize_with = "deserialize_email_globs", default)]
+    pub author: Option<GlobSet>,
+    /// Globs of committer emails.
+    #[serde(deserialize_with = "deserialize_email_globs", default)]
+    pub committer: Option<GlobSet>,
+    pub min_files_changed: Option<usize>,
+    pub max_files_changed: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TrailerCondition {
+    /// Key of the trailer, compared case-insensitively, e.g. `Reviewed-by`.
+    pub name: String,
+    /// If specified, value of the trailer must match it.
+    #[serde(deserialize_with = "deserialize_regex", default)]
+    pub value: Option<Regex>,
+}
+
+fn default_sides() -> Vec<DiffSide> {
+    vec![DiffSide::Added, DiffSide::Removed]
+}
+
+fn default_rename_similarity() -> f64 {
+    0.5
+}
+
+/// Part of a changed file, searched by trigger's pattern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffSide {
+    /// Lines added by the change.
+    Added,
+    /// Lines removed by the change.
+    Removed,
+    /// Unchanged lines surrounding the changes, e.g. signature of a function whose body changed.
+    Context,
+    /// Whole file, as it was before the change.
+    OldFile,
+    /// Whole file, as it is after the change.
+    NewFile,
+}
+
+/// Language of a file, recognized by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    C,
+    Cpp,
+    CSharp,
+    Dart,
+    Go,
+    Java,
+    JavaScript,
+    Kotlin,
+    Php,
+    Python,
+    Ruby,
+    Rust,
+    Scala,
+    Swift,
+    TypeScript,
+}
+
+impl Language {
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            Language::C => &["c", "h"],
+            Language::Cpp => &["cpp", "cc", "cxx", "hpp", "hh", "hxx"],
+            Language::CSharp => &["cs"],
+            Language::Dart => &["dart"],
+            Language::Go => &["go"],
+            Language::Java => &["java"],
+            Language::JavaScript => &["js", "jsx", "mjs", "cjs"],
+            Language::Kotlin => &["kt", "kts"],
+            Language::Php => &["php"],
+            Language::Python => &["py", "pyi"],
+            Language::Ruby => &["rb"],
+            Language::Rust => &["rs"],
+            Language::Scala => &["scala", "sc"],
+            Language::Swift => &["swift"],
+            Language::TypeScript => &["ts", "tsx", "mts", "cts"],
+        }
+    }
+}
+
+/// Describes what to look for in the repository, once the rule fired.
+#[derive(Debug, Deserialize)]
+pub struct Matcher {
+    /// Only files under this path are searched. Defaults to the whole repository.
+    pub path: Option<Box<Path>>,
+    /// May contain `$transform` placeholder, replaced with result of the rule's transform.
+    pub pattern: String,
+    /// Result of the transform is put into `pattern` as is, rather than escaped,
+    /// so it can be a regex itself.
+    #[serde(default)]
+    pub raw: bool,
+}
+
+/// Ordered from the least to the most severe.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, clap::ValueEnum,
+)]
 pub enum AlertLevel {
     Info,
     Warn,
@@ -57,18 +671,28 @@ pub enum AlertLevel {
 
 #[derive(Debug, Deserialize)]
 pub struct Action {
-    // pub alert_level: AlertLevel,
-    // pub message: String,
+    pub alert_level: AlertLevel,
+    pub message: String,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Rule {
+    pub name: String,
+    pub trigger: TriggerNode,
+    #[serde(default)]
     pub transform: Transform,
+    pub matcher: Matcher,
+    pub action: Action,
 }
 
+/// Extension of files from `scripts_dir`, which are registered as custom transforms.
+const SCRIPT_EXTENSION: &str = "rhai";
+
 pub struct CustomStep {
     pub name: String,
     pub script: String,
+    /// Function of the script called by the step, `transform` if unspecified.
+    pub function: Option<String>,
 }
 
 impl Config {
@@ -89,35 +713,248 @@ impl Config {
             }
         };
 
-        let cfg = serde_yaml::from_str(&yaml_content)?;
+        let mut cfg: Config = serde_yaml::from_str(&yaml_content)?;
+        cfg.base_dir = file_path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        cfg.resolve_transforms()?;
+        cfg.validate_rules()?;
         debug!("Deserialized config:\n{}", cfg);
 
         Ok(cfg)
     }
 
-    pub fn custom_transform_scripts(&self) -> Option<Vec<CustomStep>> {
-        let scripts: Vec<CustomStep> = self
-            .rules
+    /// Replaces steps of every rule's transform with the full list of steps to run,
+    /// including the ones of transforms it extends.
+    fn resolve_transforms(&mut self) -> Result<(), ConfigError> {
+        let mut named = HashMap::new();
+        for transform in &self.transforms {
+            let name = transform
+                .name
+                .as_deref()
+                .ok_or(ConfigError::UnnamedTransform)?;
+            if named.insert(name, transform).is_some() {
+                return Err(ConfigError::DuplicateTransform {
+                    name: name.to_string(),
+                });
+            }
+        }
+
+        // Every named transform is validated, even if no rule references it
+        for transform in &self.transforms {
+            let mut chain = transform.name.iter().cloned().collect();
+            resolve_steps(transform, &named, &mut chain)?;
+        }
+
+        let mut resolved = Vec::with_capacity(self.rules.len());
+        for rule in &self.rules {
+            let mut chain = vec![format!("rule {}", rule.name)];
+            resolved.push(resolve_steps(&rule.transform, &named, &mut chain)?);
+        }
+
+        for (rule, steps) in self.rules.iter_mut().zip(resolved) {
+            rule.transform.steps = steps;
+        }
+
+        Ok(())
+    }
+
+    /// Rejects rules, which can not be evaluated, although each of their sections is valid.
+    fn validate_rules(&self) -> Result<(), ConfigError> {
+        // Findings are reported under the name of their rule
+        let mut names = HashSet::new();
+        for rule in &self.rules {
+            if !names.insert(rule.name.as_str()) {
+                return Err(ConfigError::DuplicateRule {
+                    name: rule.name.clone(),
+                });
+            }
+            // Without a file, negated trigger fires for the analysed revision, with no value to capture
+            if rule.trigger.is_commit_only() && rule.trigger.has_negation() {
+                return Err(ConfigError::InvalidTrigger {
+                    rule: rule.name.clone(),
+                    msg:
+                        "`not` of commit conditions must be combined with file conditions by `all`"
+                            .to_string(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Collects custom transforms from `scripts_dir`, and from steps named `custom*`,
+    /// which have either inline `script`, or `file` with the script, in args.
+    pub fn custom_transform_scripts(&self) -> Result<Option<Vec<CustomStep>>, ConfigError> {
+        let mut scripts = match &self.options.scripts_dir {
+            Some(dir) => self.scripts_from_dir(&self.base_dir.join(dir))?,
+            None => Vec::new(),
+        };
+        let mut from_steps: Vec<CustomStep> = Vec::new();
+
+        // Named transforms are included even if no rule extends them,
+        // so their scripts fail the config load as well
+        let run_steps = self
+            .transforms
             .iter()
-            .flat_map(|rule| &rule.transform.steps)
-            .filter(|step| step.name.starts_with("custom"))
-            .filter_map(|step| {
-                step.args
-                    .as_ref()
-                    .and_then(|args| args.get("script"))
-                    .and_then(|script_value| script_value.as_str())
-                    .map(|s| CustomStep {
+            .chain(self.rules.iter().map(|rule| &rule.transform))
+            .flat_map(|transform| transform.run_steps());
+
+        for step in run_steps {
+            let Some(args) = &step.args else {
+                continue;
+            };
+            if !step.name.starts_with("custom") {
+                match args.get("script").is_some() || args.get("file").is_some() {
+                    true => {
+                        return Err(ConfigError::InvalidCustomStep {
+                            name: step.name.to_owned(),
+                            msg: "only steps named 'custom*' can run a 'script' or 'file'"
+                                .to_string(),
+                        })
+                    }
+                    false => continue,
+                }
+            }
+            let inline = args.get("script").and_then(|value| value.as_str());
+            let file = args.get("file").and_then(|value| value.as_str());
+
+            let script = match (inline, file) {
+                (Some(_), Some(_)) => {
+                    return Err(ConfigError::InvalidCustomStep {
                         name: step.name.to_owned(),
-                        script: s.to_string(),
+                        msg: "only one of 'script' and 'file' can be specified".to_string(),
                     })
-            })
-            .collect();
+                }
+                (Some(script), None) => script.to_string(),
+                (None, Some(file)) => read_script(&self.base_dir.join(file))?,
+                (None, None) => continue,
+            };
+
+            let custom = CustomStep {
+                name: step.name.to_owned(),
+                script,
+                function: args
+                    .get("function")
+                    .and_then(|function| function.as_str())
+                    .map(String::from),
+            };
+
+            // Scripts are registered by the step name, so it has to identify a single script
+            match from_steps.iter().find(|other| other.name == custom.name) {
+                Some(other)
+                    if other.script == custom.script && other.function == custom.function =>
+                {
+                    continue
+                }
+                Some(_) => {
+                    return Err(ConfigError::InvalidCustomStep {
+                        name: custom.name,
+                        msg: "step with the same name runs a different script".to_string(),
+                    })
+                }
+                None => from_steps.push(custom),
+            }
+        }
+        if let Some(clash) = from_steps
+            .iter()
+            .find(|custom| scripts.iter().any(|script| script.name == custom.name))
+        {
+            return Err(ConfigError::InvalidCustomStep {
+                name: clash.name.clone(),
+                msg: "name is already taken by a script from scripts_dir".to_string(),
+            });
+        }
+        scripts.extend(from_steps);
 
         match &scripts.is_empty() {
-            true => None,
-            false => Some(scripts),
+            true => Ok(None),
+            false => Ok(Some(scripts)),
         }
     }
+
+    fn scripts_from_dir(&self, dir: &Path) -> Result<Vec<CustomStep>, ConfigError> {
+        let to_read_failure = |e: std::io::Error| ConfigError::ScriptReadFailure {
+            path: String::from(dir.to_string_lossy()),
+            msg: e.to_string(),
+        };
+
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir).map_err(to_read_failure)? {
+            let path = entry.map_err(to_read_failure)?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == SCRIPT_EXTENSION) {
+                paths.push(path);
+            }
+        }
+        // Makes the order, in which scripts are registered, deterministic
+        paths.sort();
+
+        let mut scripts = Vec::new();
+        for path in paths {
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            if is_standard_transform(name) {
+                return Err(ConfigError::InvalidCustomStep {
+                    name: name.to_string(),
+                    msg: format!("{:?} overrides a standard transform", path),
+                });
+            }
+            debug!("Found custom transform {} in {:?}", name, path);
+            scripts.push(CustomStep {
+                name: name.to_string(),
+                script: read_script(&path)?,
+                function: None,
+            });
+        }
+
+        Ok(scripts)
+    }
+}
+
+/// `chain` holds names of transforms being resolved, the last of which is `transform`.
+fn resolve_steps(
+    transform: &Transform,
+    named: &HashMap<&str, &Transform>,
+    chain: &mut Vec<String>,
+) -> Result<Vec<TransformStep>, ConfigError> {
+    let mut steps = match &transform.extends {
+        Some(extends) => {
+            if chain.contains(extends) {
+                chain.push(extends.clone());
+                return Err(ConfigError::TransformCycle {
+                    cycle: chain.join(" -> "),
+                });
+            }
+            let parent =
+                named
+                    .get(extends.as_str())
+                    .ok_or_else(|| ConfigError::UnknownTransform {
+                        name: extends.clone(),
+                        referenced_by: chain.last().cloned().unwrap_or_default(),
+                    })?;
+
+            chain.push(extends.clone());
+            let steps = resolve_steps(parent, named, chain)?;
+            chain.pop();
+            steps
+        }
+        None => Vec::new(),
+    };
+    steps.extend(transform.steps.iter().cloned());
+
+    Ok(steps)
+}
+
+fn read_script(path: &Path) -> Result<String, ConfigError> {
+    std::fs::read_to_string(path).map_err(|e| {
+        error!("Failed to read script from {:?}", path);
+        ConfigError::ScriptReadFailure {
+            path: String::from(path.to_string_lossy()),
+            msg: e.to_string(),
+        }
+    })
 }
 
 impl fmt::Display for RepositoryConfig {
@@ -156,6 +993,98 @@ impl fmt::Display for Config {
     }
 }
 
+fn deserialize_regex<'a, D>(deserializer: D) -> Result<Option<Regex>, D::Error>
+where
+    D: Deserializer<'a>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    match Regex::new(&pattern) {
+        Ok(regex) => Ok(Some(regex)),
+        Err(e) => Err(serde::de::Error::custom(e)),
+    }
+}
+
+fn deserialize_globs<'a, D>(deserializer: D) -> Result<Option<Gitignore>, D::Error>
+where
+    D: Deserializer<'a>,
+{
+    let patterns = Vec::<String>::deserialize(deserializer)?;
+    // Empty list would match no file, rather than leave files unfiltered
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    // Paths from the diff are relative to the root of the repository
+    let mut builder = GitignoreBuilder::new("");
+    for pattern in &patterns {
+        builder
+            .add_line(None, pattern)
+            .map_err(serde::de::Error::custom)?;
+    }
+    match builder.build() {
+        Ok(gitignore) => Ok(Some(gitignore)),
+        Err(e) => Err(serde::de::Error::custom(e)),
+    }
+}
+
+fn deserialize_email_globs<'a, D>(deserializer: D) -> Result<Option<GlobSet>, D::Error>
+where
+    D: Deserializer<'a>,
+{
+    let patterns = Vec::<String>::deserialize(deserializer)?;
+    let mut builder = GlobSetBuilder::new();
+    for pattern in &patterns {
+        let glob = GlobBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map_err(serde::de::Error::custom)?;
+        builder.add(glob);
+    }
+    match builder.build() {
+        Ok(globs) => Ok(Some(globs)),
+        Err(e) => Err(serde::de::Error::custom(e)),
+    }
+}
+
+fn deserialize_glob<'a, D>(deserializer: D) -> Result<Option<GlobMatcher>, D::Error>
+where
+    D: Deserializer<'a>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    // `*` does not match `/`, so only `**` matches any number of directories
+    match GlobBuilder::new(&pattern).literal_separator(true).build() {
+        Ok(glob) => Ok(Some(glob.compile_matcher())),
+        Err(e) => Err(serde::de::Error::custom(e)),
+    }
+}
+
+fn deserialize_percentage<'a, D>(deserializer: D) -> Result<u16, D::Error>
+where
+    D: Deserializer<'a>,
+{
+    let percentage = u16::deserialize(deserializer)?;
+    match percentage <= 100 {
+        true => Ok(percentage),
+        false => Err(serde::de::Error::custom(format!(
+            "percentage must be between 0 and 100, got {}",
+            percentage
+        ))),
+    }
+}
+
+fn deserialize_ratio<'a, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'a>,
+{
+    let ratio = f64::deserialize(deserializer)?;
+    match (0.0..=1.0).contains(&ratio) {
+        true => Ok(ratio),
+        false => Err(serde::de::Error::custom(format!(
+            "ratio must be between 0 and 1, got {}",
+            ratio
+        ))),
+    }
+}
+
 fn deserialize_url<'a, D>(deserializer: D) -> Result<Option<Url>, D::Error>
 where
     D: Deserializer<'a>,
@@ -166,3 +1095,416 @@ where
         Err(e) => Err(serde::de::Error::custom(e)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loads config from `yaml`, written to a temporary file.
+    fn load(yaml: &str) -> Result<Config> {
+        let dir = std::env::temp_dir().join(format!("impactifier-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("impactifier-config.yaml");
+        std::fs::write(&path, yaml).unwrap();
+
+        let cfg = Config::load_from_file(&path);
+        let _ = std::fs::remove_dir_all(&dir);
+        cfg
+    }
+
+    /// Names of custom transforms collected from config with `transform` and `scripts_dir`
+    /// holding `scripts`, given as pairs of file name and script.
+    fn custom_scripts(
+        transform: &str,
+        scripts: &[(&str, &str)],
+    ) -> Result<Vec<String>, ConfigError> {
+        let dir = std::env::temp_dir().join(format!("impactifier-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("scripts")).unwrap();
+        for (file, script) in scripts {
+            std::fs::write(dir.join("scripts").join(file), script).unwrap();
+        }
+        let path = dir.join("impactifier-config.yaml");
+        let yaml = with_transforms("[]", transform)
+            .replace("options: {}", "options: {scripts_dir: scripts}");
+        std::fs::write(&path, yaml).unwrap();
+
+        let scripts = Config::load_from_file(&path)
+            .unwrap()
+            .custom_transform_scripts();
+        let _ = std::fs::remove_dir_all(&dir);
+        scripts.map(|scripts| {
+            scripts
+                .unwrap_or_default()
+                .into_iter()
+                .map(|script| script.name)
+                .collect()
+        })
+    }
+
+    fn load_err(yaml: &str) -> ConfigError {
+        match load(yaml) {
+            Ok(_) => panic!("config should be rejected"),
+            Err(err) => err.downcast().unwrap(),
+        }
+    }
+
+    /// Config with `transforms` section and a single rule with `trigger` and `transform`.
+    fn config(transforms: &str, trigger: &str, transform: &str) -> String {
+        format!(
+            r#"
+repository: {{}}
+options: {{}}
+transforms: {}
+rules:
+  - name: handlers
+    trigger: {}
+    transform: {}
+    matcher:
+      pattern: $transform
+    action:
+      alert_level: Warn
+      message: Handler changed
+"#,
+            transforms, trigger, transform
+        )
+    }
+
+    fn with_transforms(transforms: &str, transform: &str) -> String {
+        config(transforms, r"{pattern: 'func (\w+)Handler'}", transform)
+    }
+
+    fn with_trigger(trigger: &str) -> String {
+        config("[]", trigger, "{}")
+    }
+
+    fn trigger_err(yaml: &str) -> String {
+        match serde_yaml::from_str::<TriggerNode>(yaml) {
+            Ok(_) => panic!("trigger {} should be rejected", yaml),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    fn step_names(rule: &Rule) -> Vec<&str> {
+        rule.transform
+            .run_steps()
+            .into_iter()
+            .map(|step| step.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn rule_transform_runs_steps_of_extended_transforms_first() {
+        let cfg = load(&with_transforms(
+            r#"
+              - {name: base, steps: [{name: toLowerCase}]}
+              - {name: endpoint, extends: base, steps: [{name: toKebabCase}]}
+            "#,
+            "{name: toApiEndpoint, extends: endpoint, steps: [{name: trim}]}",
+        ))
+        .unwrap();
+
+        assert_eq!(
+            step_names(&cfg.rules[0]),
+            vec!["toLowerCase", "toKebabCase", "trim"]
+        );
+    }
+
+    #[test]
+    fn rule_transform_name_is_only_a_label() {
+        // Name of a named transform does not make the rule extend it
+        let cfg = load(&with_transforms(
+            "[{name: base, steps: [{name: toLowerCase}]}]",
+            "{name: base, steps: [{name: trim}]}",
+        ))
+        .unwrap();
+
+        assert_eq!(step_names(&cfg.rules[0]), vec!["trim"]);
+    }
+
+    #[test]
+    fn unknown_extended_transform_is_rejected() {
+        let err = load_err(&with_transforms("[]", "{extends: missing}"));
+        assert!(
+            matches!(
+                &err,
+                ConfigError::UnknownTransform { name, referenced_by }
+                    if name == "missing" && referenced_by == "rule handlers"
+            ),
+            "{}",
+            err
+        );
+
+        let err = load_err(&with_transforms("[{name: unused, extends: missing}]", "{}"));
+        assert!(
+            matches!(
+                &err,
+                ConfigError::UnknownTransform { name, referenced_by }
+                    if name == "missing" && referenced_by == "unused"
+            ),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn transform_cycles_are_rejected() {
+        let cases = [
+            ("[{name: a, extends: a}]", "{}", "a -> a"),
+            (
+                "[{name: a, extends: b}, {name: b, extends: c}, {name: c, extends: a}]",
+                "{}",
+                "a -> b -> c -> a",
+            ),
+            (
+                "[{name: a, extends: b}, {name: b, extends: a}]",
+                "{extends: b}",
+                "a -> b -> a",
+            ),
+        ];
+
+        for (transforms, transform, expected) in cases {
+            let err = load_err(&with_transforms(transforms, transform));
+            assert!(
+                matches!(&err, ConfigError::TransformCycle { cycle } if cycle.ends_with(expected)),
+                "{}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn named_transforms_must_have_unique_names() {
+        let err = load_err(&with_transforms("[{steps: []}]", "{}"));
+        assert!(matches!(err, ConfigError::UnnamedTransform), "{}", err);
+
+        let err = load_err(&with_transforms("[{name: a}, {name: a}]", "{}"));
+        assert!(
+            matches!(&err, ConfigError::DuplicateTransform { name } if name == "a"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn trigger_groups_must_not_be_empty() {
+        assert!(trigger_err("{all: []}").contains("`all` must contain at least one trigger"));
+        assert!(trigger_err("{any: []}").contains("`any` must contain at least one trigger"));
+    }
+
+    #[test]
+    fn all_with_negated_sibling_is_rejected() {
+        let err = trigger_err("{all: [{path: api/}, {not: {path: api/}}]}");
+        assert!(err.contains("along with its negation"), "{}", err);
+        let err =
+            trigger_err("{all: [{any: [{status: [added]}]}, {not: {any: [{status: [added]}]}}]}");
+        assert!(err.contains("along with its negation"), "{}", err);
+
+        let valid = [
+            "{all: [{path: api/}, {not: {path: client/}}]}",
+            // Globs can not be compared, so they are never considered the same
+            "{all: [{include: ['*.go']}, {not: {include: ['*.go']}}]}",
+            "{any: [{path: api/}, {not: {path: api/}}]}",
+        ];
+        for yaml in valid {
+            assert!(
+                serde_yaml::from_str::<TriggerNode>(yaml).is_ok(),
+                "{}",
+                yaml
+            );
+        }
+    }
+
+    #[test]
+    fn all_with_disjoint_file_conditions_is_rejected() {
+        let err = trigger_err("{all: [{status: [added]}, {status: [deleted, renamed]}]}");
+        assert!(err.contains("`status`"), "{}", err);
+        let err = trigger_err("{all: [{extensions: [go]}, {extensions: [ts]}]}");
+        assert!(err.contains("`extensions` or `languages`"), "{}", err);
+        let err = trigger_err("{all: [{languages: [go]}, {extensions: [.ts]}]}");
+        assert!(err.contains("`extensions` or `languages`"), "{}", err);
+
+        let valid = [
+            "{all: [{status: [added, modified]}, {status: [modified]}, {path: api/}]}",
+            "{all: [{languages: [typescript]}, {extensions: [.ts]}]}",
+            // Conditions of nested groups are not narrowed
+            "{all: [{extensions: [go]}, {any: [{extensions: [ts]}]}]}",
+        ];
+        for yaml in valid {
+            assert!(
+                serde_yaml::from_str::<TriggerNode>(yaml).is_ok(),
+                "{}",
+                yaml
+            );
+        }
+    }
+
+    #[test]
+    fn have_common_value_narrows_every_condition() {
+        let cases: &[(&[&[u8]], bool)] = &[
+            (&[], true),
+            (&[&[1, 2]], true),
+            (&[&[1, 2], &[2, 3]], true),
+            (&[&[1, 2], &[2, 3], &[3]], false),
+            (&[&[1], &[2]], false),
+            (&[&[]], false),
+        ];
+
+        for (conditions, expected) in cases {
+            let values = conditions.iter().map(|condition| condition.to_vec());
+            assert_eq!(have_common_value(values), *expected, "{:?}", conditions);
+        }
+    }
+
+    #[test]
+    fn not_of_commit_conditions_requires_file_conditions() {
+        let invalid = [
+            "{not: {commit: {breaking: true}}}",
+            r"{all: [{commit: {message: 'rename (\w+)'}}, {not: {commit: {breaking: true}}}]}",
+        ];
+        for trigger in invalid {
+            let err = load_err(&with_trigger(trigger));
+            assert!(
+                matches!(&err, ConfigError::InvalidTrigger { rule, .. } if rule == "handlers"),
+                "{}",
+                err
+            );
+        }
+
+        let cfg = load(&with_trigger(
+            "{all: [{path: api/}, {not: {commit: {breaking: true}}}]}",
+        ));
+        assert!(cfg.is_ok(), "{:?}", cfg.err());
+    }
+
+    #[test]
+    fn custom_scripts_are_collected_from_steps_and_scripts_dir() {
+        let names = custom_scripts(
+            r#"{steps: [
+                {name: toApiPath},
+                {name: customSuffix, args: {script: 'context.matched_string'}},
+                {name: customSuffix, args: {script: 'context.matched_string'}}
+            ]}"#,
+            &[
+                ("toApiPath.rhai", "context.matched_string"),
+                ("notes.txt", ""),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(names, ["toApiPath", "customSuffix"]);
+    }
+
+    #[test]
+    fn custom_script_names_must_not_collide() {
+        let cases = [
+            (
+                r#"{steps: [
+                    {name: customSuffix, args: {script: 'context.matched_string'}},
+                    {name: customSuffix, args: {script: '"Dto"'}}
+                ]}"#,
+                vec![],
+                "customSuffix",
+                "runs a different script",
+            ),
+            (
+                "{steps: [{name: customPath, args: {script: 'context.matched_string'}}]}",
+                vec![("customPath.rhai", "context.matched_string")],
+                "customPath",
+                "already taken by a script from scripts_dir",
+            ),
+            (
+                "{}",
+                vec![("toSnakeCase.rhai", "context.matched_string")],
+                "toSnakeCase",
+                "overrides a standard transform",
+            ),
+            (
+                "{steps: [{name: toSnakeCase, args: {script: 'context.matched_string'}}]}",
+                vec![],
+                "toSnakeCase",
+                "only steps named 'custom*'",
+            ),
+        ];
+
+        for (transform, scripts, expected_name, expected_msg) in cases {
+            match custom_scripts(transform, &scripts) {
+                Err(ConfigError::InvalidCustomStep { name, msg }) => {
+                    assert_eq!(name, expected_name, "{}", transform);
+                    assert!(msg.contains(expected_msg), "{}: {}", transform, msg);
+                }
+                other => panic!("{} should be rejected, got {:?}", transform, other),
+            }
+        }
+    }
+
+    #[test]
+    fn custom_scripts_of_unused_named_transforms_are_collected() {
+        let cfg = load(&with_transforms(
+            "[{name: unused, steps: [{name: customSuffix, args: {script: 'context.matched_string'}}]}]",
+            "{}",
+        ))
+        .unwrap();
+
+        let scripts = cfg.custom_transform_scripts().unwrap().unwrap();
+        assert_eq!(scripts[0].name, "customSuffix");
+    }
+
+    #[test]
+    fn similarity_threshold_must_be_a_percentage() {
+        let yaml = with_trigger("{}");
+        let threshold = |value: &str| {
+            yaml.replace(
+                "options: {}",
+                &format!("options: {{renames: {{similarity_threshold: {}}}}}", value),
+            )
+        };
+
+        let cfg = load(&threshold("100")).unwrap();
+        assert_eq!(cfg.options.renames.similarity_threshold, 100);
+
+        let err = load(&threshold("101")).unwrap_err().to_string();
+        assert!(err.contains("between 0 and 100, got 101"), "{}", err);
+    }
+
+    #[test]
+    fn rename_similarity_must_be_a_ratio() {
+        for valid in ["0", "0.5", "1"] {
+            let trigger = format!("{{pattern: a, rename_similarity: {}}}", valid);
+            assert!(
+                serde_yaml::from_str::<TriggerNode>(&trigger).is_ok(),
+                "{}",
+                valid
+            );
+        }
+
+        for (invalid, expected) in [
+            ("1.5", "got 1.5"),
+            ("-0.1", "got -0.1"),
+            (".nan", "got NaN"),
+        ] {
+            let err = trigger_err(&format!("{{pattern: a, rename_similarity: {}}}", invalid));
+            assert!(err.contains(expected), "{}: {}", invalid, err);
+        }
+    }
+
+    #[test]
+    fn empty_globs_are_unspecified() {
+        let trigger: Trigger = serde_yaml::from_str("{include: [], exclude: []}").unwrap();
+
+        assert!(trigger.include.is_none());
+        assert!(trigger.exclude.is_none());
+        assert!(!trigger.has_file_conditions());
+    }
+
+    #[test]
+    fn rule_names_must_be_unique() {
+        let rule = with_trigger("{}");
+        let rules = rule.split_once("rules:\n").unwrap().1;
+        let yaml = format!("{}{}", rule, rules);
+
+        assert!(matches!(
+            load_err(&yaml),
+            ConfigError::DuplicateRule { name } if name == "handlers"
+        ));
+    }
+}
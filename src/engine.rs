@@ -0,0 +1,1722 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use git2::Repository;
+use ignore::WalkBuilder;
+use regex::{Captures, Regex, RegexSet};
+use serde::Serialize;
+use thiserror::Error;
+use tracing::{debug, error, info, trace, warn};
+
+use crate::config::{AlertLevel, CommitTrigger, DiffSide, Rule, Trigger, TriggerNode};
+use crate::git::{CommitInfo, Diff, FileDiff, LineOrigin};
+use crate::transform::{self, Context, StepOutput, TransformError};
+
+/// Placeholder in `Matcher::pattern`, replaced with result of the rule's transform.
+pub const TRANSFORM_PLACEHOLDER: &str = "$transform";
+
+#[derive(Error, Debug)]
+pub enum RuleError {
+    #[error("Rule {} failed to transform {}. Error: {}", rule, value, err)]
+    TransformFailure {
+        rule: String,
+        value: String,
+        err: TransformError,
+    },
+    #[error(
+        "Rule {} has invalid matcher pattern {}. Error: {}",
+        rule,
+        pattern,
+        err
+    )]
+    InvalidMatcherPattern {
+        rule: String,
+        pattern: String,
+        err: regex::Error,
+    },
+    #[error("Custom transform {} of rule {} exceeded {} limit", step, rule, limit)]
+    ScriptLimitExceeded {
+        rule: String,
+        step: String,
+        limit: String,
+    },
+    #[error("Repository has no working directory to search in")]
+    BareRepository,
+    #[error("Failed to search {}. Error: {}", path, err)]
+    SearchFailure { path: String, err: std::io::Error },
+    #[error("Failed to read content of {}. Error: {}", path, err)]
+    ContentReadFailure { path: String, err: anyhow::Error },
+    #[error("Failed to search revision {}. Error: {}", revision, err)]
+    RevisionSearchFailure { revision: String, err: git2::Error },
+    #[error("Failed to read files changed by commit {}. Error: {}", sha, err)]
+    CommitReadFailure { sha: String, err: git2::Error },
+}
+
+/// Change in the diff, which made the rule's trigger fire.
+#[derive(Debug, Serialize)]
+pub struct TriggerMatch {
+    /// `None` if the trigger fired for a commit, rather than a file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<PathBuf>,
+    /// Line in the old version of the file for removed lines and the old file,
+    /// or in the new one otherwise.
+    /// `None` if the trigger has no pattern, and fired for the whole file.
+    pub line: Option<u32>,
+    pub side: Option<DiffSide>,
+    pub matched: String,
+    /// Input of the rule's transform: first capture group of the trigger pattern,
+    /// or the whole match, if pattern has no groups.
+    pub captured: String,
+    /// Capture group named `class` of the trigger pattern, passed to the transform as `class_name`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class_name: Option<String>,
+    /// How the captured name was changed. `None` if the trigger has no pattern.
+    pub change: Option<CaptureChange>,
+    /// Commit satisfying commit conditions of the trigger, if it has any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+}
+
+/// Change of a name captured by trigger's pattern, found by comparing
+/// both versions of the file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum CaptureChange {
+    /// Name appears only in the new version of the file.
+    Added,
+    /// Name appears only in the old version of the file.
+    Removed,
+    /// Name appears in both versions, and in some of the changed lines.
+    Modified,
+    /// Name appears in both versions, but none of the changed lines.
+    Unchanged,
+    /// Name disappeared, and a similar one appeared in its place.
+    RenamedTo { name: String },
+    /// Name appeared in place of a similar one, which disappeared.
+    RenamedFrom { name: String },
+}
+
+/// Place in the repository, found by the rule's matcher.
+#[derive(Debug, Clone, Serialize)]
+pub struct MatcherHit {
+    pub file_path: PathBuf,
+    pub line: u32,
+    /// Column of the first character of the match, counted in characters from 1.
+    pub column: u32,
+    /// Whole line, which contains the match.
+    pub content: String,
+    /// Value produced by the transform, which was found at this place.
+    pub variant: String,
+}
+
+/// Impact of a change detected by a rule, which is passed to the rule's action.
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    /// Findings are grouped by rule in the report, so the name is not repeated.
+    #[serde(skip)]
+    pub rule: String,
+    pub trigger: TriggerMatch,
+    /// Every candidate value produced by the transform.
+    pub transformed: Vec<String>,
+    /// Values produced by each step of the rule's transform, useful for debugging it.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub transform_steps: Vec<StepOutput>,
+    pub hits: Vec<MatcherHit>,
+    pub alert_level: AlertLevel,
+    pub message: String,
+}
+
+/// Evaluates every rule against the diff, and performs actions of the rules
+/// which found an impact.
+///
+/// For each rule: trigger → transform → matcher → action.
+pub fn run_rules(
+    repo: &Repository,
+    rules: &[Rule],
+    diff: &Diff,
+) -> Result<Vec<Finding>, RuleError> {
+    let source = search_source(repo, diff)?;
+
+    let mut findings = Vec::new();
+    for rule in rules {
+        trace!("Evaluating rule {}", rule.name);
+        let rule_findings = run_rule(repo, &source, rule, diff)?;
+        info!(
+            "Rule {} finished with {} finding(s)",
+            rule.name,
+            rule_findings.len()
+        );
+
+        rule_findings.iter().for_each(perform_action);
+        findings.extend(rule_findings);
+    }
+
+    Ok(findings)
+}
+
+fn run_rule(
+    repo: &Repository,
+    source: &SearchSource,
+    rule: &Rule,
+    diff: &Diff,
+) -> Result<Vec<Finding>, RuleError> {
+    let trigger_matches = find_trigger_matches(repo, &rule.trigger, diff)?;
+    debug!(
+        "Rule {} triggered {} time(s)",
+        rule.name,
+        trigger_matches.len()
+    );
+
+    let mut transformed_matches = Vec::with_capacity(trigger_matches.len());
+    for trigger_match in trigger_matches {
+        let (transformed, transform_steps) = apply_transform(rule, &trigger_match)?;
+        transformed_matches.push((trigger_match, transformed, transform_steps));
+    }
+
+    // The same value is often captured in a few places, e.g. when a line is modified,
+    // so every distinct variant is searched for once, all of them in a single pass.
+    // Empty variants are left out, as they would match every line.
+    let mut seen = HashSet::new();
+    let variants: Vec<String> = transformed_matches
+        .iter()
+        .flat_map(|(_, transformed, _)| transformed)
+        .filter(|variant| !variant.is_empty() && seen.insert(variant.as_str()))
+        .cloned()
+        .collect();
+    let searches = match variants.is_empty() {
+        true => HashMap::new(),
+        false => search_matcher(repo, source, rule, &VariantPatterns::new(rule, variants)?)?,
+    };
+
+    let mut findings = Vec::new();
+    for (trigger_match, transformed, transform_steps) in transformed_matches {
+        let hits: Vec<MatcherHit> = transformed
+            .iter()
+            .filter_map(|variant| searches.get(variant))
+            .flatten()
+            .cloned()
+            .collect();
+
+        if hits.is_empty() {
+            trace!("No matches found for {:?}", transformed);
+            continue;
+        }
+
+        findings.push(Finding {
+            rule: rule.name.clone(),
+            trigger: trigger_match,
+            hits,
+            transformed,
+            transform_steps,
+            alert_level: rule.action.alert_level,
+            message: rule.action.message.clone(),
+        });
+    }
+
+    Ok(findings)
+}
+
+fn find_trigger_matches(
+    repo: &Repository,
+    trigger: &TriggerNode,
+    diff: &Diff,
+) -> Result<Vec<TriggerMatch>, RuleError> {
+    let matches = match trigger.is_commit_only() {
+        true => evaluate_trigger(repo, trigger, diff, None)?.unwrap_or_default(),
+        false => {
+            let mut matches = Vec::new();
+            for file in &diff.files {
+                if let Some(file_matches) = evaluate_trigger(repo, trigger, diff, Some(file))? {
+                    matches.extend(file_matches);
+                }
+            }
+            matches
+        }
+    };
+
+    // Commit conditions combined with file ones by `any` fire the same way for every file
+    let mut seen = HashSet::new();
+    Ok(matches
+        .into_iter()
+        .filter(|trigger_match| {
+            seen.insert((
+                trigger_match.file_path.clone(),
+                trigger_match.commit.clone(),
+                trigger_match.captured.clone(),
+            ))
+        })
+        .collect())
+}
+
+/// Evaluates the trigger for a single changed file, or for the whole range if `file` is `None`.
+/// Returns `None` if the trigger did not fire.
+fn evaluate_trigger(
+    repo: &Repository,
+    trigger: &TriggerNode,
+    diff: &Diff,
+    file: Option<&FileDiff>,
+) -> Result<Option<Vec<TriggerMatch>>, RuleError> {
+    match trigger {
+        TriggerNode::Leaf(trigger) => leaf_matches(repo, trigger, diff, file),
+        TriggerNode::All(triggers) => {
+            let mut matches = Vec::new();
+            for trigger in triggers {
+                match evaluate_trigger(repo, trigger, diff, file)? {
+                    Some(trigger_matches) => matches.extend(trigger_matches),
+                    None => return Ok(None),
+                }
+            }
+            Ok(Some(merge_matches(matches)))
+        }
+        TriggerNode::Any(triggers) => {
+            for trigger in triggers {
+                if let Some(matches) = evaluate_trigger(repo, trigger, diff, file)? {
+                    return Ok(Some(matches));
+                }
+            }
+            Ok(None)
+        }
+        TriggerNode::Not(trigger) => match evaluate_trigger(repo, trigger, diff, file)? {
+            Some(_) => Ok(None),
+            None => Ok(Some(vec![negated_match(diff, file)])),
+        },
+    }
+}
+
+fn leaf_matches(
+    repo: &Repository,
+    trigger: &Trigger,
+    diff: &Diff,
+    file: Option<&FileDiff>,
+) -> Result<Option<Vec<TriggerMatch>>, RuleError> {
+    // Commit conditions are cheaper to check than content of the files
+    let commit = match &trigger.commit {
+        Some(commit_trigger) => {
+            let mut commits = Vec::new();
+            for commit in &diff.commits {
+                if commit_matches(repo, commit_trigger, commit)? {
+                    commits.push(commit);
+                }
+            }
+            if commits.is_empty() {
+                return Ok(None);
+            }
+            if !trigger.has_file_conditions() {
+                return Ok(Some(commit_trigger_matches(
+                    commit_trigger,
+                    commits.into_iter(),
+                )));
+            }
+            commits.first().map(|commit| commit.sha.clone())
+        }
+        None => None,
+    };
+
+    let Some(file) = file.filter(|file| file_matches(trigger, file)) else {
+        return Ok(None);
+    };
+    let file_path = file.path().unwrap_or(Path::new("")).to_path_buf();
+
+    let mut matches = match &trigger.pattern {
+        Some(pattern) => find_file_matches(repo, trigger, pattern, file, &file_path)?,
+        // Without a pattern, every change of the file fires the trigger
+        None => vec![file_match(file_path)],
+    };
+    if matches.is_empty() {
+        return Ok(None);
+    }
+
+    for trigger_match in &mut matches {
+        trigger_match.commit = commit.clone();
+    }
+    Ok(Some(matches))
+}
+
+/// Match of the whole file, rather than its content.
+fn file_match(file_path: PathBuf) -> TriggerMatch {
+    TriggerMatch {
+        captured: file_path.to_string_lossy().to_string(),
+        matched: file_path.to_string_lossy().to_string(),
+        file_path: Some(file_path),
+        line: None,
+        side: None,
+        class_name: None,
+        change: None,
+        commit: None,
+    }
+}
+
+/// Match of `not`, which fired because the negated trigger did not.
+/// Without a file, it is a match of the analysed revision.
+fn negated_match(diff: &Diff, file: Option<&FileDiff>) -> TriggerMatch {
+    match file {
+        Some(file) => file_match(file.path().unwrap_or(Path::new("")).to_path_buf()),
+        None => TriggerMatch {
+            file_path: None,
+            line: None,
+            side: None,
+            matched: diff.to.name.clone(),
+            captured: diff.to.name.clone(),
+            class_name: None,
+            change: None,
+            commit: diff.to.sha.clone(),
+        },
+    }
+}
+
+/// Combines matches of every trigger of `all` group. More specific matches, of the file's
+/// content over the whole file, and of files over commits, are kept over the others.
+fn merge_matches(matches: Vec<TriggerMatch>) -> Vec<TriggerMatch> {
+    let commit = matches
+        .iter()
+        .find_map(|trigger_match| trigger_match.commit.clone());
+    let has_file_matches = matches
+        .iter()
+        .any(|trigger_match| trigger_match.file_path.is_some());
+    let has_line_matches = matches
+        .iter()
+        .any(|trigger_match| trigger_match.line.is_some());
+
+    let mut seen = HashSet::new();
+    matches
+        .into_iter()
+        .filter(|trigger_match| !has_file_matches || trigger_match.file_path.is_some())
+        .filter(|trigger_match| !has_line_matches || trigger_match.line.is_some())
+        .filter(|trigger_match| seen.insert(trigger_match.captured.clone()))
+        .map(|mut trigger_match| {
+            if trigger_match.commit.is_none() {
+                trigger_match.commit = commit.clone();
+            }
+            trigger_match
+        })
+        .collect()
+}
+
+/// Fires once for every distinct value captured from the matching commits.
+/// Without `message` pattern, summary of the commit is captured.
+fn commit_trigger_matches<'a>(
+    trigger: &CommitTrigger,
+    commits: impl Iterator<Item = &'a CommitInfo>,
+) -> Vec<TriggerMatch> {
+    let mut seen = HashSet::new();
+    let mut matches = Vec::new();
+
+    for commit in commits {
+        let (matched, captured, class_name) = match trigger
+            .message
+            .as_ref()
+            .and_then(|message| message.captures(&commit.message))
+        {
+            Some(captures) => {
+                let matched = captures.get(0).map_or("", |m| m.as_str());
+                let captured = captures.get(1).map_or(matched, |m| m.as_str());
+                (
+                    matched.to_string(),
+                    captured.to_string(),
+                    class_name(&captures),
+                )
+            }
+            None => (
+                commit.summary().to_string(),
+                commit.summary().to_string(),
+                None,
+            ),
+        };
+
+        if !seen.insert(captured.clone()) {
+            continue;
+        }
+
+        matches.push(TriggerMatch {
+            file_path: None,
+            line: None,
+            side: None,
+            matched,
+            captured,
+            class_name,
+            change: None,
+            commit: Some(commit.sha.clone()),
+        });
+    }
+
+    matches
+}
+
+fn commit_matches(
+    repo: &Repository,
+    trigger: &CommitTrigger,
+    commit: &CommitInfo,
+) -> Result<bool, RuleError> {
+    if let Some(message) = &trigger.message {
+        if !message.is_match(&commit.message) {
+            return Ok(false);
+        }
+    }
+
+    if !trigger.types.is_empty() || !trigger.scopes.is_empty() {
+        let Some(conventional) = commit.conventional() else {
+            return Ok(false);
+        };
+        if !trigger.types.is_empty()
+            && !trigger
+                .types
+                .iter()
+                .any(|kind| kind.eq_ignore_ascii_case(conventional.kind))
+        {
+            return Ok(false);
+        }
+        if !trigger.scopes.is_empty()
+            && !conventional
+                .scope
+                .is_some_and(|scope| trigger.scopes.iter().any(|expected| expected == scope))
+        {
+            return Ok(false);
+        }
+    }
+
+    if let Some(breaking) = trigger.breaking {
+        if commit.is_breaking() != breaking {
+            return Ok(false);
+        }
+    }
+
+    if !trigger.trailers.is_empty() {
+        let trailers = commit.trailers();
+        let has_trailers = trigger.trailers.iter().all(|condition| {
+            trailers.iter().any(|(key, value)| {
+                key.eq_ignore_ascii_case(&condition.name)
+                    && condition
+                        .value
+                        .as_ref()
+                        .is_none_or(|pattern| pattern.is_match(value))
+            })
+        });
+        if !has_trailers {
+            return Ok(false);
+        }
+    }
+
+    if let Some(author) = &trigger.author {
+        if !author.is_match(&commit.author.email) {
+            return Ok(false);
+        }
+    }
+
+    if let Some(committer) = &trigger.committer {
+        if !committer.is_match(&commit.committer.email) {
+            return Ok(false);
+        }
+    }
+
+    // Counting changed files takes a diff of the commit, so it is done only when needed
+    if trigger.min_files_changed.is_none() && trigger.max_files_changed.is_none() {
+        return Ok(true);
+    }
+    let files_changed = commit
+        .files_changed(repo)
+        .map_err(|err| RuleError::CommitReadFailure {
+            sha: commit.sha.clone(),
+            err,
+        })?;
+
+    Ok(trigger
+        .min_files_changed
+        .is_none_or(|min| files_changed >= min)
+        && trigger
+            .max_files_changed
+            .is_none_or(|max| files_changed <= max))
+}
+
+/// Searches the sides of a single file specified by the trigger, and tells how each
+/// captured name was changed.
+fn find_file_matches(
+    repo: &Repository,
+    trigger: &Trigger,
+    pattern: &Regex,
+    file: &FileDiff,
+    file_path: &Path,
+) -> Result<Vec<TriggerMatch>, RuleError> {
+    let to_read_failure = |err| RuleError::ContentReadFailure {
+        path: file_path.to_string_lossy().to_string(),
+        err,
+    };
+    let needs_content = |side| trigger.sides.contains(&side);
+
+    let mut old_content = None;
+    let mut new_content = None;
+    if needs_content(DiffSide::OldFile) {
+        old_content = Some(file.read_old(repo).map_err(to_read_failure)?);
+    }
+    if needs_content(DiffSide::NewFile) {
+        new_content = Some(file.read_new(repo).map_err(to_read_failure)?);
+    }
+
+    let mut seen = HashSet::new();
+    let mut matches = Vec::new();
+    for side in &trigger.sides {
+        let lines: Vec<(Option<u32>, &str)> = match side {
+            DiffSide::Added => changed_lines(file, LineOrigin::Added),
+            DiffSide::Removed => changed_lines(file, LineOrigin::Removed),
+            DiffSide::Context => changed_lines(file, LineOrigin::Context),
+            DiffSide::OldFile => content_lines(old_content.as_ref()),
+            DiffSide::NewFile => content_lines(new_content.as_ref()),
+        };
+
+        for (line, content) in lines {
+            for captures in pattern.captures_iter(content) {
+                let matched = captures.get(0).map_or("", |m| m.as_str());
+                let captured = captures.get(1).map_or(matched, |m| m.as_str());
+
+                if !seen.insert(captured.to_string()) {
+                    continue;
+                }
+
+                matches.push(TriggerMatch {
+                    file_path: Some(file_path.to_path_buf()),
+                    line,
+                    side: Some(*side),
+                    matched: matched.to_string(),
+                    captured: captured.to_string(),
+                    class_name: class_name(&captures),
+                    change: None,
+                    commit: None,
+                });
+            }
+        }
+    }
+
+    if matches.is_empty() {
+        return Ok(matches);
+    }
+
+    // Both versions are compared as a whole, so a name which only moved within the file,
+    // or is still used elsewhere in it, is not reported as removed
+    let old_content = match old_content {
+        Some(content) => content,
+        None => file.read_old(repo).map_err(to_read_failure)?,
+    };
+    let new_content = match new_content {
+        Some(content) => content,
+        None => file.read_new(repo).map_err(to_read_failure)?,
+    };
+    let old_names = match &old_content {
+        Some(content) => captured_names(pattern, content.lines()),
+        None => captured_names(pattern, changed_content(file, LineOrigin::Removed)),
+    };
+    let new_names = match &new_content {
+        Some(content) => captured_names(pattern, content.lines()),
+        None => captured_names(pattern, changed_content(file, LineOrigin::Added)),
+    };
+    let touched_names = captured_names(
+        pattern,
+        file.lines()
+            .filter(|line| line.origin != LineOrigin::Context)
+            .map(|line| line.content.as_str()),
+    );
+    let renames = pair_renames(&old_names, &new_names, trigger.rename_similarity);
+
+    for trigger_match in &mut matches {
+        let name = &trigger_match.captured;
+        let change = match (old_names.contains(name), new_names.contains(name)) {
+            (true, true) if touched_names.contains(name) => CaptureChange::Modified,
+            (true, true) => CaptureChange::Unchanged,
+            (true, false) => match renames.iter().find(|(old, _)| old == name) {
+                Some((_, new)) => CaptureChange::RenamedTo { name: new.clone() },
+                None => CaptureChange::Removed,
+            },
+            (false, true) => match renames.iter().find(|(_, new)| new == name) {
+                Some((old, _)) => CaptureChange::RenamedFrom { name: old.clone() },
+                None => CaptureChange::Added,
+            },
+            // Name is split between lines, which were searched separately
+            (false, false) => CaptureChange::Modified,
+        };
+        trigger_match.change = Some(change);
+    }
+
+    Ok(matches)
+}
+
+fn changed_lines(file: &FileDiff, origin: LineOrigin) -> Vec<(Option<u32>, &str)> {
+    file.lines()
+        .filter(|line| line.origin == origin)
+        .map(|line| {
+            let line_number = match origin {
+                LineOrigin::Removed => line.old_lineno,
+                LineOrigin::Added | LineOrigin::Context => line.new_lineno,
+            };
+            (line_number, line.content.as_str())
+        })
+        .collect()
+}
+
+fn changed_content(file: &FileDiff, origin: LineOrigin) -> impl Iterator<Item = &str> {
+    file.lines()
+        .filter(move |line| line.origin == origin)
+        .map(|line| line.content.as_str())
+}
+
+fn content_lines(content: Option<&Option<String>>) -> Vec<(Option<u32>, &str)> {
+    content
+        .and_then(|content| content.as_deref())
+        .map(|content| {
+            content
+                .lines()
+                .enumerate()
+                .map(|(idx, line)| (Some(idx as u32 + 1), line))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn captured_names<'a>(pattern: &Regex, lines: impl Iterator<Item = &'a str>) -> HashSet<String> {
+    let mut names = HashSet::new();
+    for line in lines {
+        for captures in pattern.captures_iter(line) {
+            let matched = captures.get(0).map_or("", |m| m.as_str());
+            names.insert(captures.get(1).map_or(matched, |m| m.as_str()).to_string());
+        }
+    }
+    names
+}
+
+/// Pairs names which disappeared from the file with the most similar ones which appeared in it.
+/// Each name is paired at most once, and only if it is at least `min_similarity` similar.
+fn pair_renames(
+    old_names: &HashSet<String>,
+    new_names: &HashSet<String>,
+    min_similarity: f64,
+) -> Vec<(String, String)> {
+    let mut candidates = Vec::new();
+    for old in old_names.difference(new_names) {
+        for new in new_names.difference(old_names) {
+            let score = similarity(old, new);
+            if score >= min_similarity {
+                candidates.push((score, old, new));
+            }
+        }
+    }
+    // Ties are broken by names, so the pairs do not depend on the order of the sets
+    candidates.sort_by(|a, b| {
+        b.0.total_cmp(&a.0)
+            .then_with(|| a.1.cmp(b.1))
+            .then_with(|| a.2.cmp(b.2))
+    });
+
+    let mut paired = HashSet::new();
+    let mut renames = Vec::new();
+    for (_, old, new) in candidates {
+        if paired.contains(old) || paired.contains(new) {
+            continue;
+        }
+        paired.insert(old);
+        paired.insert(new);
+        renames.push((old.clone(), new.clone()));
+    }
+
+    renames
+}
+
+/// Similarity of two names from 0 to 1, based on their edit distance.
+fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    1.0 - previous[b.len()] as f64 / longest as f64
+}
+
+/// Checks file conditions of the trigger. Renamed and copied files match,
+/// if either their old or new path does.
+fn file_matches(trigger: &Trigger, file: &FileDiff) -> bool {
+    if !trigger.status.is_empty() && !trigger.status.contains(&file.status) {
+        return false;
+    }
+
+    [&file.old_path, &file.new_path]
+        .into_iter()
+        .flatten()
+        .any(|file_path| path_matches(trigger, file_path))
+}
+
+fn path_matches(trigger: &Trigger, file_path: &Path) -> bool {
+    if let Some(path) = &trigger.path {
+        if !file_path.starts_with(path) {
+            return false;
+        }
+    }
+
+    if let Some(include) = &trigger.include {
+        if !include
+            .matched_path_or_any_parents(file_path, false)
+            .is_ignore()
+        {
+            return false;
+        }
+    }
+
+    if let Some(exclude) = &trigger.exclude {
+        if exclude
+            .matched_path_or_any_parents(file_path, false)
+            .is_ignore()
+        {
+            return false;
+        }
+    }
+
+    if trigger.extensions.is_empty() && trigger.languages.is_empty() {
+        return true;
+    }
+
+    let Some(extension) = file_path.extension().and_then(|ext| ext.to_str()) else {
+        return false;
+    };
+    let has_extension = trigger
+        .extensions
+        .iter()
+        .any(|expected| expected.trim_start_matches('.') == extension);
+    let has_language = trigger
+        .languages
+        .iter()
+        .any(|language| language.extensions().contains(&extension));
+
+    has_extension || has_language
+}
+
+/// Name of the class, captured by the group named `class`.
+fn class_name(captures: &Captures) -> Option<String> {
+    captures
+        .name("class")
+        .map(|class_name| class_name.as_str().to_string())
+}
+
+/// Runs the rule's transform steps on the captured value.
+fn apply_transform(
+    rule: &Rule,
+    trigger_match: &TriggerMatch,
+) -> Result<(Vec<String>, Vec<StepOutput>), RuleError> {
+    let context = Context {
+        matched_string: trigger_match.captured.clone(),
+        file_path: trigger_match.file_path.clone().unwrap_or_default(),
+        class_name: trigger_match.class_name.clone(),
+    };
+
+    let output =
+        transform::run_pipeline(&rule.transform.steps, context).map_err(|err| {
+            match err.exceeded_limit() {
+                Some((step, limit)) => RuleError::ScriptLimitExceeded {
+                    rule: rule.name.clone(),
+                    step: step.to_string(),
+                    limit: limit.to_string(),
+                },
+                None => RuleError::TransformFailure {
+                    rule: rule.name.clone(),
+                    value: trigger_match.captured.clone(),
+                    err,
+                },
+            }
+        })?;
+
+    Ok((output.values, output.steps))
+}
+
+/// Files, which matcher searches in.
+enum SearchSource<'r> {
+    /// Working directory, if the target revision is checked out, or is not a commit.
+    WorkingTree(&'r Path),
+    /// Tree of the target revision, read from the object database.
+    Revision { name: String, tree: git2::Tree<'r> },
+}
+
+/// Matcher searches the repository at the target revision of the diff.
+/// It is read from the working directory when possible, to include uncommitted changes.
+fn search_source<'r>(repo: &'r Repository, diff: &Diff) -> Result<SearchSource<'r>, RuleError> {
+    let to_search_failure = |err| RuleError::RevisionSearchFailure {
+        revision: diff.to.name.clone(),
+        err,
+    };
+
+    let Some(sha) = &diff.to.sha else {
+        let root = repo.workdir().ok_or(RuleError::BareRepository)?;
+        return Ok(SearchSource::WorkingTree(root));
+    };
+
+    let head = repo
+        .head()
+        .and_then(|head| head.peel_to_commit())
+        .map(|commit| commit.id().to_string())
+        .ok();
+    if let (Some(root), true) = (repo.workdir(), head.as_ref() == Some(sha)) {
+        return Ok(SearchSource::WorkingTree(root));
+    }
+
+    trace!(
+        "Target revision {} is not checked out, reading its tree",
+        sha
+    );
+    let tree = git2::Oid::from_str(sha)
+        .and_then(|id| repo.find_commit(id))
+        .and_then(|commit| commit.tree())
+        .map_err(to_search_failure)?;
+
+    Ok(SearchSource::Revision {
+        name: diff.to.name.clone(),
+        tree,
+    })
+}
+
+/// Matcher patterns of every variant searched for by a rule.
+struct VariantPatterns {
+    variants: Vec<String>,
+    regexes: Vec<Regex>,
+    /// Tells which of `regexes` match a line, without searching it with each of them.
+    set: RegexSet,
+}
+
+impl VariantPatterns {
+    fn new(rule: &Rule, variants: Vec<String>) -> Result<Self, RuleError> {
+        let mut patterns = Vec::with_capacity(variants.len());
+        let mut regexes = Vec::with_capacity(variants.len());
+        for variant in &variants {
+            let value = match rule.matcher.raw {
+                true => variant.to_string(),
+                false => regex::escape(variant),
+            };
+            let pattern = rule.matcher.pattern.replace(TRANSFORM_PLACEHOLDER, &value);
+            let regex = Regex::new(&pattern).map_err(|err| RuleError::InvalidMatcherPattern {
+                rule: rule.name.clone(),
+                pattern: pattern.clone(),
+                err,
+            })?;
+            patterns.push(pattern);
+            regexes.push(regex);
+        }
+
+        let set = RegexSet::new(&patterns).map_err(|err| RuleError::InvalidMatcherPattern {
+            rule: rule.name.clone(),
+            pattern: rule.matcher.pattern.clone(),
+            err,
+        })?;
+
+        Ok(Self {
+            variants,
+            regexes,
+            set,
+        })
+    }
+}
+
+/// Searches files of the matcher for every variant at once. Hits are grouped by variant.
+fn search_matcher(
+    repo: &Repository,
+    source: &SearchSource,
+    rule: &Rule,
+    patterns: &VariantPatterns,
+) -> Result<HashMap<String, Vec<MatcherHit>>, RuleError> {
+    let mut hits = HashMap::new();
+    match source {
+        SearchSource::WorkingTree(root) => {
+            let search_root = match &rule.matcher.path {
+                Some(path) => root.join(path),
+                None => root.to_path_buf(),
+            };
+            match search_root.exists() {
+                true => search_working_tree(root, &search_root, patterns, &mut hits)?,
+                false => warn!("Matcher path {:?} does not exist", search_root),
+            }
+        }
+        SearchSource::Revision { name, tree } => {
+            let path = rule.matcher.path.as_deref();
+            search_revision(repo, name, tree, path, patterns, &mut hits)?;
+        }
+    }
+
+    Ok(hits)
+}
+
+/// Searches files under `path`, skipping the ones ignored by `.gitignore`.
+fn search_working_tree(
+    root: &Path,
+    path: &Path,
+    patterns: &VariantPatterns,
+    hits: &mut HashMap<String, Vec<MatcherHit>>,
+) -> Result<(), RuleError> {
+    let to_search_failure = |err| RuleError::SearchFailure {
+        path: String::from(path.to_string_lossy()),
+        err,
+    };
+
+    // Hidden files, like CI configs, may reference the changed code as well
+    let walker = WalkBuilder::new(path)
+        .hidden(false)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+
+    for entry in walker {
+        let entry = entry.map_err(|err| to_search_failure(std::io::Error::other(err)))?;
+        if !entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_file())
+        {
+            continue;
+        }
+
+        // Files which are not valid UTF-8 are most likely binary, so they are skipped
+        if let Ok(content) = fs::read_to_string(entry.path()) {
+            let file_path = entry.path().strip_prefix(root).unwrap_or(entry.path());
+            search_content(file_path, &content, patterns, hits);
+        }
+    }
+
+    Ok(())
+}
+
+/// Searches blobs under `path` in the tree of a revision.
+fn search_revision(
+    repo: &Repository,
+    name: &str,
+    tree: &git2::Tree,
+    path: Option<&Path>,
+    patterns: &VariantPatterns,
+    hits: &mut HashMap<String, Vec<MatcherHit>>,
+) -> Result<(), RuleError> {
+    let to_search_failure = |err| RuleError::RevisionSearchFailure {
+        revision: name.to_string(),
+        err,
+    };
+
+    let mut blobs = Vec::new();
+    let subtree = match path {
+        Some(path) => match tree.get_path(path) {
+            Ok(entry) if entry.kind() == Some(git2::ObjectType::Blob) => {
+                blobs.push((path.to_path_buf(), entry.id()));
+                None
+            }
+            Ok(entry) => Some((
+                path.to_path_buf(),
+                entry
+                    .to_object(repo)
+                    .and_then(|object| object.peel_to_tree())
+                    .map_err(to_search_failure)?,
+            )),
+            Err(err) if err.code() == git2::ErrorCode::NotFound => {
+                warn!("Matcher path {:?} does not exist in {}", path, name);
+                return Ok(());
+            }
+            Err(err) => return Err(to_search_failure(err)),
+        },
+        None => Some((PathBuf::new(), tree.clone())),
+    };
+
+    if let Some((prefix, subtree)) = subtree {
+        subtree
+            .walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+                // Submodules are not a part of the tree
+                if entry.kind() == Some(git2::ObjectType::Blob) {
+                    let file_name = String::from_utf8_lossy(entry.name_bytes()).to_string();
+                    blobs.push((prefix.join(dir).join(file_name), entry.id()));
+                }
+                git2::TreeWalkResult::Ok
+            })
+            .map_err(to_search_failure)?;
+    }
+
+    for (file_path, id) in blobs {
+        let blob = repo.find_blob(id).map_err(to_search_failure)?;
+        if blob.is_binary() {
+            continue;
+        }
+        if let Ok(content) = std::str::from_utf8(blob.content()) {
+            search_content(&file_path, content, patterns, hits);
+        }
+    }
+
+    Ok(())
+}
+
+fn search_content(
+    file_path: &Path,
+    content: &str,
+    patterns: &VariantPatterns,
+    hits: &mut HashMap<String, Vec<MatcherHit>>,
+) {
+    for (idx, line) in content.lines().enumerate() {
+        for matched in patterns.set.matches(line).iter() {
+            let variant = &patterns.variants[matched];
+            for found in patterns.regexes[matched].find_iter(line) {
+                hits.entry(variant.clone()).or_default().push(MatcherHit {
+                    file_path: file_path.to_path_buf(),
+                    line: idx as u32 + 1,
+                    column: line[..found.start()].chars().count() as u32 + 1,
+                    content: line.to_string(),
+                    variant: variant.clone(),
+                });
+            }
+        }
+    }
+}
+
+fn perform_action(finding: &Finding) {
+    let trigger = &finding.trigger;
+    let location = match (&trigger.file_path, trigger.line, &trigger.commit) {
+        (Some(file_path), Some(line), _) => format!("{}:{}", file_path.to_string_lossy(), line),
+        (Some(file_path), None, _) => file_path.to_string_lossy().to_string(),
+        (None, _, Some(commit)) => format!("commit {}", commit),
+        (None, _, None) => String::new(),
+    };
+    let change = match &trigger.change {
+        Some(CaptureChange::Added) => "added".to_string(),
+        Some(CaptureChange::Removed) => "removed".to_string(),
+        Some(CaptureChange::Modified) => "modified".to_string(),
+        Some(CaptureChange::Unchanged) => "found".to_string(),
+        Some(CaptureChange::RenamedTo { name }) => format!("renamed to `{}`", name),
+        Some(CaptureChange::RenamedFrom { name }) => format!("renamed from `{}`", name),
+        None => "changed".to_string(),
+    };
+    let summary = format!(
+        "[{}] {}: `{}` {} at {}, transformed into `{}`, affects {} place(s)",
+        finding.rule,
+        finding.message,
+        trigger.matched,
+        change,
+        location,
+        finding.transformed.join("`, `"),
+        finding.hits.len()
+    );
+
+    match finding.alert_level {
+        AlertLevel::Info => info!("{}", summary),
+        AlertLevel::Warn => warn!("{}", summary),
+        AlertLevel::Severe => error!("{}", summary),
+    }
+
+    for hit in &finding.hits {
+        debug!(
+            "  {}:{}:{}: {} (`{}`)",
+            hit.file_path.to_string_lossy(),
+            hit.line,
+            hit.column,
+            hit.content.trim(),
+            hit.variant
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{RenamesConfig, ScriptLimitsConfig};
+    use crate::git::{self, DiffOptions, FileStatus, Hunk, Line};
+
+    /// Empty repository in a new temporary directory, removed once it is dropped.
+    struct TempRepo {
+        dir: PathBuf,
+        repo: Repository,
+    }
+
+    impl TempRepo {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("impactifier-{}", uuid::Uuid::new_v4()));
+            let repo = Repository::init(&dir).unwrap();
+            Self { dir, repo }
+        }
+
+        /// Commits `files` as the whole content of the tree on top of `parent`,
+        /// and points `branch` to the commit.
+        fn commit(
+            &self,
+            branch: &str,
+            parent: Option<git2::Oid>,
+            message: &str,
+            files: &[(&str, &str)],
+        ) -> git2::Oid {
+            let mut index = self.repo.index().unwrap();
+            index.clear().unwrap();
+            for (path, content) in files {
+                let full_path = self.dir.join(path);
+                fs::create_dir_all(full_path.parent().unwrap()).unwrap();
+                fs::write(&full_path, content).unwrap();
+                index.add_path(Path::new(path)).unwrap();
+            }
+            let tree = self.repo.find_tree(index.write_tree().unwrap()).unwrap();
+            let parents: Vec<git2::Commit> = parent
+                .map(|id| self.repo.find_commit(id).unwrap())
+                .into_iter()
+                .collect();
+            let parents: Vec<&git2::Commit> = parents.iter().collect();
+
+            let signature = git2::Signature::now("John", "john@doe.com").unwrap();
+            self.repo
+                .commit(
+                    Some(&format!("refs/heads/{}", branch)),
+                    &signature,
+                    &signature,
+                    message,
+                    &tree,
+                    &parents,
+                )
+                .unwrap()
+        }
+
+        /// Checks out `branch` into the working directory, discarding any changes.
+        fn checkout(&self, branch: &str) {
+            self.repo
+                .set_head(&format!("refs/heads/{}", branch))
+                .unwrap();
+            self.repo
+                .checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+                .unwrap();
+        }
+    }
+
+    impl Drop for TempRepo {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// Diff of a single hunk with `lines`. Versions of the file, which are given,
+    /// are written to the object database, so they can be compared as a whole.
+    fn file_diff(
+        repo: &Repository,
+        old: Option<&str>,
+        new: Option<&str>,
+        lines: &[(LineOrigin, &str)],
+    ) -> FileDiff {
+        let (mut old_lineno, mut new_lineno) = (0, 0);
+        let lines = lines
+            .iter()
+            .map(|(origin, content)| {
+                let (old, new) = match origin {
+                    LineOrigin::Added => (false, true),
+                    LineOrigin::Removed => (true, false),
+                    LineOrigin::Context => (true, true),
+                };
+                old_lineno += u32::from(old);
+                new_lineno += u32::from(new);
+                Line {
+                    origin: *origin,
+                    old_lineno: old.then_some(old_lineno),
+                    new_lineno: new.then_some(new_lineno),
+                    content: content.to_string(),
+                }
+            })
+            .collect();
+        let status = match (old, new) {
+            (None, _) => FileStatus::Added,
+            (_, None) => FileStatus::Deleted,
+            _ => FileStatus::Modified,
+        };
+
+        FileDiff {
+            old_path: old.map(|_| PathBuf::from("api/h.go")),
+            new_path: new.map(|_| PathBuf::from("api/h.go")),
+            status,
+            is_binary: false,
+            mode_change: None,
+            hunks: vec![Hunk {
+                header: String::new(),
+                old_start: 1,
+                old_lines: old_lineno,
+                new_start: 1,
+                new_lines: new_lineno,
+                lines,
+            }],
+            old_id: old.map(|content| repo.blob(content.as_bytes()).unwrap()),
+            new_id: new.map(|content| repo.blob(content.as_bytes()).unwrap()),
+        }
+    }
+
+    fn trigger(yaml: &str) -> Trigger {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    /// Captured names along with how they were changed, in order they were found.
+    fn changes(
+        repo: &Repository,
+        trigger: &Trigger,
+        file: &FileDiff,
+    ) -> Vec<(String, CaptureChange)> {
+        let pattern = trigger.pattern.as_ref().unwrap();
+        find_file_matches(repo, trigger, pattern, file, file.path().unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|trigger_match| (trigger_match.captured, trigger_match.change.unwrap()))
+            .collect()
+    }
+
+    fn names(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn find_file_matches_classifies_changes_of_captured_names() {
+        let temp = TempRepo::new();
+        let old = "func GetUser() {}\nfunc DeleteUser() {}\nfunc ListOrders() {}\nfunc Keep() {}\n";
+        let new = "func FetchUser() {}\nfunc ListOrders() { x }\nfunc Keep() {}\nfunc CreateInvoice() {}\n";
+        let file = file_diff(
+            &temp.repo,
+            Some(old),
+            Some(new),
+            &[
+                (LineOrigin::Removed, "func GetUser() {}"),
+                (LineOrigin::Removed, "func DeleteUser() {}"),
+                (LineOrigin::Removed, "func ListOrders() {}"),
+                (LineOrigin::Added, "func FetchUser() {}"),
+                (LineOrigin::Added, "func ListOrders() { x }"),
+                (LineOrigin::Context, "func Keep() {}"),
+                (LineOrigin::Added, "func CreateInvoice() {}"),
+            ],
+        );
+        let trigger = trigger("{pattern: 'func (\\w+)\\(', sides: [removed, added, context]}");
+
+        assert_eq!(
+            changes(&temp.repo, &trigger, &file),
+            vec![
+                (
+                    "GetUser".to_string(),
+                    CaptureChange::RenamedTo {
+                        name: "FetchUser".to_string()
+                    }
+                ),
+                ("DeleteUser".to_string(), CaptureChange::Removed),
+                ("ListOrders".to_string(), CaptureChange::Modified),
+                (
+                    "FetchUser".to_string(),
+                    CaptureChange::RenamedFrom {
+                        name: "GetUser".to_string()
+                    }
+                ),
+                ("CreateInvoice".to_string(), CaptureChange::Added),
+                ("Keep".to_string(), CaptureChange::Unchanged),
+            ]
+        );
+    }
+
+    #[test]
+    fn find_file_matches_does_not_report_moved_names_as_removed() {
+        let temp = TempRepo::new();
+        let file = file_diff(
+            &temp.repo,
+            Some("func GetUser() {}\nfunc ListOrders() {}\n"),
+            Some("func ListOrders() {}\nfunc GetUser() {}\n"),
+            &[
+                (LineOrigin::Removed, "func GetUser() {}"),
+                (LineOrigin::Context, "func ListOrders() {}"),
+                (LineOrigin::Added, "func GetUser() {}"),
+            ],
+        );
+        let trigger = trigger("{pattern: 'func (\\w+)\\('}");
+
+        assert_eq!(
+            changes(&temp.repo, &trigger, &file),
+            vec![("GetUser".to_string(), CaptureChange::Modified)]
+        );
+    }
+
+    #[test]
+    fn find_file_matches_in_added_and_deleted_files() {
+        let temp = TempRepo::new();
+        let trigger = trigger("{pattern: 'func (\\w+)\\('}");
+
+        let added = file_diff(
+            &temp.repo,
+            None,
+            Some("func GetUser() {}\n"),
+            &[(LineOrigin::Added, "func GetUser() {}")],
+        );
+        assert_eq!(
+            changes(&temp.repo, &trigger, &added),
+            vec![("GetUser".to_string(), CaptureChange::Added)]
+        );
+
+        let deleted = file_diff(
+            &temp.repo,
+            Some("func GetUser() {}\n"),
+            None,
+            &[(LineOrigin::Removed, "func GetUser() {}")],
+        );
+        assert_eq!(
+            changes(&temp.repo, &trigger, &deleted),
+            vec![("GetUser".to_string(), CaptureChange::Removed)]
+        );
+    }
+
+    #[test]
+    fn find_file_matches_pairs_renames_above_similarity() {
+        let temp = TempRepo::new();
+        let file = file_diff(
+            &temp.repo,
+            Some("func GetUser() {}\n"),
+            Some("func FetchUser() {}\n"),
+            &[
+                (LineOrigin::Removed, "func GetUser() {}"),
+                (LineOrigin::Added, "func FetchUser() {}"),
+            ],
+        );
+
+        let strict = trigger("{pattern: 'func (\\w+)\\(', rename_similarity: 0.9}");
+        assert_eq!(
+            changes(&temp.repo, &strict, &file),
+            vec![
+                ("FetchUser".to_string(), CaptureChange::Added),
+                ("GetUser".to_string(), CaptureChange::Removed),
+            ]
+        );
+    }
+
+    #[test]
+    fn pair_renames_pairs_most_similar_names_once() {
+        let renames = pair_renames(
+            &names(&["getUser", "deleteUser"]),
+            &names(&["fetchUser", "deleteUsers"]),
+            0.5,
+        );
+        assert_eq!(
+            renames,
+            vec![
+                ("deleteUser".to_string(), "deleteUsers".to_string()),
+                ("getUser".to_string(), "fetchUser".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn pair_renames_breaks_ties_by_names() {
+        // Both old names are equally similar to the new one
+        let renames = pair_renames(&names(&["ac", "ab"]), &names(&["ad"]), 0.5);
+        assert_eq!(renames, vec![("ab".to_string(), "ad".to_string())]);
+
+        let renames = pair_renames(&names(&["ad"]), &names(&["ac", "ab"]), 0.5);
+        assert_eq!(renames, vec![("ad".to_string(), "ab".to_string())]);
+    }
+
+    #[test]
+    fn pair_renames_skips_names_present_on_both_sides() {
+        let renames = pair_renames(&names(&["getUser"]), &names(&["getUser", "getUsers"]), 0.5);
+        assert!(renames.is_empty());
+    }
+
+    #[test]
+    fn similarity_is_based_on_edit_distance() {
+        let cases = [
+            ("", "", 1.0),
+            ("getUser", "getUser", 1.0),
+            ("abc", "abd", 2.0 / 3.0),
+            ("GetUser", "FetchUser", 1.0 - 3.0 / 9.0),
+            ("abc", "", 0.0),
+            ("abc", "xyz", 0.0),
+        ];
+
+        for (a, b, expected) in cases {
+            assert!(
+                (similarity(a, b) - expected).abs() < 1e-9,
+                "similarity of `{}` and `{}` is {}",
+                a,
+                b,
+                similarity(a, b)
+            );
+            assert_eq!(similarity(a, b), similarity(b, a));
+        }
+    }
+
+    /// Repository with `main`, on which handlers are called by the client, and `feature`,
+    /// on which `GetUser` handler is renamed to `FetchUser`, and `DeleteUser` is removed.
+    /// `feature` is checked out.
+    fn handlers_repo() -> TempRepo {
+        let temp = TempRepo::new();
+        let client = "call('GetUser')\ncall('DeleteUser')\ncall('ListOrders')\n";
+        let main = temp.commit(
+            "main",
+            None,
+            "feat: add handlers",
+            &[
+                (
+                    "api/h.go",
+                    "func GetUserHandler() {}\nfunc DeleteUserHandler() {}\nfunc ListOrdersHandler() {}\n",
+                ),
+                ("client/c.ts", client),
+            ],
+        );
+        temp.commit(
+            "feature",
+            Some(main),
+            "feat(api)!: rename user handler",
+            &[
+                (
+                    "api/h.go",
+                    "func FetchUserHandler() {}\nfunc ListOrdersHandler() {}\n",
+                ),
+                ("client/c.ts", client),
+            ],
+        );
+        temp.checkout("feature");
+        transform::init_registry(None, &ScriptLimitsConfig::default()).unwrap();
+        temp
+    }
+
+    fn feature_diff(repo: &Repository) -> Diff {
+        let options = DiffOptions::Branches {
+            head: "feature",
+            base: "main",
+        };
+        git::extract_difference(repo, "origin", &options, &RenamesConfig::default()).unwrap()
+    }
+
+    fn rule(yaml: &str) -> Rule {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    const HANDLERS_RULE: &str = r#"
+        name: handlers
+        trigger:
+          path: api/
+          pattern: 'func (\w+)Handler'
+        matcher:
+          path: client/
+          pattern: "'$transform'"
+        action:
+          alert_level: Warn
+          message: Handler changed
+    "#;
+
+    #[test]
+    fn run_rules_finds_usages_of_changed_names() {
+        let temp = handlers_repo();
+        let diff = feature_diff(&temp.repo);
+
+        let findings = run_rules(&temp.repo, &[rule(HANDLERS_RULE)], &diff).unwrap();
+
+        // `FetchUser` is not used by the client yet, so it has no finding
+        let summary: Vec<_> = findings
+            .iter()
+            .map(|finding| {
+                let hit = &finding.hits[0];
+                (
+                    finding.trigger.captured.as_str(),
+                    finding.trigger.change.clone(),
+                    hit.file_path.to_string_lossy().to_string(),
+                    hit.line,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "GetUser",
+                    Some(CaptureChange::RenamedTo {
+                        name: "FetchUser".to_string()
+                    }),
+                    "client/c.ts".to_string(),
+                    1
+                ),
+                (
+                    "DeleteUser",
+                    Some(CaptureChange::Removed),
+                    "client/c.ts".to_string(),
+                    2
+                ),
+            ]
+        );
+        let finding = &findings[0];
+        assert_eq!(finding.rule, "handlers");
+        assert_eq!(finding.transformed, vec!["GetUser".to_string()]);
+        assert_eq!(finding.trigger.file_path, Some(PathBuf::from("api/h.go")));
+        assert_eq!(finding.trigger.line, Some(1));
+        assert_eq!(finding.hits.len(), 1);
+        assert_eq!(finding.hits[0].content, "call('GetUser')");
+    }
+
+    #[test]
+    fn run_rules_searches_target_revision_when_not_checked_out() {
+        let temp = handlers_repo();
+        temp.checkout("main");
+        // Working tree of another revision is not searched
+        fs::write(temp.dir.join("client/c.ts"), "").unwrap();
+        let diff = feature_diff(&temp.repo);
+
+        let findings = run_rules(&temp.repo, &[rule(HANDLERS_RULE)], &diff).unwrap();
+
+        let hits: Vec<_> = findings
+            .iter()
+            .flat_map(|finding| &finding.hits)
+            .map(|hit| (hit.line, hit.column, hit.variant.as_str()))
+            .collect();
+        assert_eq!(hits, vec![(1, 6, "GetUser"), (2, 6, "DeleteUser")]);
+    }
+
+    #[test]
+    fn run_rules_searches_working_tree_of_checked_out_target() {
+        let temp = handlers_repo();
+        // Uncommitted files are searched as well, unless they are ignored
+        fs::write(temp.dir.join("client/new.ts"), "call('FetchUser')\n").unwrap();
+        fs::write(temp.dir.join(".gitignore"), "client/ignored.ts\n").unwrap();
+        fs::write(temp.dir.join("client/ignored.ts"), "call('FetchUser')\n").unwrap();
+        let diff = feature_diff(&temp.repo);
+
+        let findings = run_rules(&temp.repo, &[rule(HANDLERS_RULE)], &diff).unwrap();
+
+        let fetch_user = findings
+            .iter()
+            .find(|finding| finding.trigger.captured == "FetchUser")
+            .unwrap();
+        let hits: Vec<_> = fetch_user
+            .hits
+            .iter()
+            .map(|hit| (hit.file_path.to_string_lossy().to_string(), hit.column))
+            .collect();
+        assert_eq!(hits, vec![("client/new.ts".to_string(), 6)]);
+    }
+
+    #[test]
+    fn run_rules_fires_for_whole_file_without_trigger_pattern() {
+        let temp = handlers_repo();
+        let diff = feature_diff(&temp.repo);
+        let rule = rule(
+            r#"
+            name: files
+            trigger:
+              path: api/
+            matcher:
+              path: client/
+              pattern: ListOrders
+            action:
+              alert_level: Info
+              message: File changed
+        "#,
+        );
+
+        let findings = run_rules(&temp.repo, &[rule], &diff).unwrap();
+
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].trigger.captured, "api/h.go");
+        assert_eq!(findings[0].trigger.line, None);
+    }
+
+    #[test]
+    fn run_rules_fires_commit_triggers_once_per_commit() {
+        let temp = handlers_repo();
+        let diff = feature_diff(&temp.repo);
+        let rule = rule(
+            r#"
+            name: breaking
+            trigger:
+              commit:
+                breaking: true
+                message: 'rename (\w+)'
+            matcher:
+              path: client/
+              pattern: "(?i)$transform"
+            action:
+              alert_level: Severe
+              message: Breaking change
+        "#,
+        );
+        let findings = run_rules(&temp.repo, &[rule], &diff).unwrap();
+
+        assert_eq!(findings.len(), 1);
+        let trigger = &findings[0].trigger;
+        assert_eq!(trigger.captured, "user");
+        assert_eq!(trigger.file_path, None);
+        assert_eq!(trigger.commit, diff.to.sha);
+        assert_eq!(findings[0].hits.len(), 2);
+        assert_eq!(findings[0].alert_level, AlertLevel::Severe);
+    }
+
+    #[test]
+    fn run_rules_fails_on_unknown_transform() {
+        let temp = handlers_repo();
+        let diff = feature_diff(&temp.repo);
+        let rule = rule(
+            r#"
+            name: handlers
+            trigger:
+              pattern: 'func (\w+)Handler'
+            transform:
+              steps:
+                - name: noSuchTransform
+            matcher:
+              pattern: $transform
+            action:
+              alert_level: Warn
+              message: Handler changed
+        "#,
+        );
+
+        let err = run_rules(&temp.repo, &[rule], &diff).unwrap_err();
+
+        assert!(
+            matches!(&err, RuleError::TransformFailure { rule, .. } if rule == "handlers"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn run_rules_fails_on_invalid_matcher_pattern() {
+        let temp = handlers_repo();
+        let diff = feature_diff(&temp.repo);
+        let rule = rule(
+            r#"
+            name: handlers
+            trigger:
+              pattern: 'func (\w+)Handler'
+            matcher:
+              pattern: ($transform
+            action:
+              alert_level: Warn
+              message: Handler changed
+        "#,
+        );
+
+        let err = run_rules(&temp.repo, &[rule], &diff).unwrap_err();
+
+        assert!(
+            matches!(&err, RuleError::InvalidMatcherPattern { rule, .. } if rule == "handlers"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn run_rules_skips_empty_variants() {
+        let temp = handlers_repo();
+        let diff = feature_diff(&temp.repo);
+        let rule = rule(
+            r#"
+            name: handlers
+            trigger:
+              pattern: 'func (\w+)Handler'
+            transform:
+              steps:
+                - name: replace
+                  args: {pattern: '.*', with: ''}
+            matcher:
+              pattern: $transform
+            action:
+              alert_level: Warn
+              message: Handler changed
+        "#,
+        );
+
+        let findings = run_rules(&temp.repo, &[rule], &diff).unwrap();
+
+        assert!(findings.is_empty(), "{:?}", findings);
+    }
+}
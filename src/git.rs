@@ -1,11 +1,16 @@
-use anyhow::Result;
-use serde::Serialize;
-use std::path::Path;
+use anyhow::{anyhow, Result};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use thiserror::Error;
 
-use git2::{Cred, CredentialType, RemoteCallbacks, Repository};
-use std::str;
-use tracing::{error, info, trace};
+use git2::{Commit, Cred, CredentialType, RemoteCallbacks, Repository};
+use tracing::{error, info, trace, warn};
+
+use crate::config::RenamesConfig;
+use crate::utils;
 use url::Url;
 
 #[derive(Error, Debug)]
@@ -25,34 +30,367 @@ pub enum GitError {
     },
     #[error("Failed to open repository from path: {}. Error: {}", path, err)]
     OpenRepositoryFailure { path: String, err: git2::Error },
+    #[error(
+        "Failed to resolve {} to a commit. Tried refs: {}.\nError: {}",
+        name,
+        candidates.join(", "),
+        err
+    )]
+    UnresolvedRef {
+        name: String,
+        candidates: Vec<String>,
+        err: git2::Error,
+    },
+    #[error(
+        "Common history of compared refs was not found within max fetch depth of {}",
+        depth
+    )]
+    HistoryTooShallow { depth: i32 },
+    #[error("Common history of compared refs was not found, even with the whole history fetched")]
+    HistoryNotFound,
 }
 
 #[derive(Debug, Serialize)]
 pub struct Diff {
-    pub deltas: Vec<FileDelta>,
+    pub from: Revision,
+    pub to: Revision,
+    pub files: Vec<FileDiff>,
+    /// Commits reachable from `to`, but not from `from`, newest first.
+    /// Empty if `to` is not a commit, like the index or working tree.
+    pub commits: Vec<CommitInfo>,
+}
+
+/// One of the compared ends of a diff.
+#[derive(Debug, Clone, Serialize)]
+pub struct Revision {
+    /// Name as specified by the user, e.g. a branch name, or description like "index".
+    pub name: String,
+    /// `None` for revisions which are not commits, like the index or working tree.
+    pub sha: Option<String>,
+}
+
+impl Revision {
+    fn commit(name: &str, commit: &Commit) -> Self {
+        Self {
+            name: name.to_string(),
+            sha: Some(commit.id().to_string()),
+        }
+    }
+
+    fn named(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            sha: None,
+        }
+    }
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+/// Changes made to a single file.
+///
+/// `old_path` is `None` for added files, and `new_path` is `None` for deleted ones.
+/// Renamed and copied files have both paths set, so a move can be told apart
+/// from a removal.
+#[derive(Debug, Serialize)]
+pub struct FileDiff {
+    pub old_path: Option<PathBuf>,
+    pub new_path: Option<PathBuf>,
+    pub status: FileStatus,
+    pub is_binary: bool,
+    pub mode_change: Option<ModeChange>,
+    pub hunks: Vec<Hunk>,
+    /// Blobs of both versions of the file, `None` if the version does not exist.
+    #[serde(skip)]
+    pub old_id: Option<git2::Oid>,
+    #[serde(skip)]
+    pub new_id: Option<git2::Oid>,
+}
+
+impl FileDiff {
+    /// Path of the file after the change, or before it, if file was deleted.
+    pub fn path(&self) -> Option<&Path> {
+        self.new_path.as_deref().or(self.old_path.as_deref())
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &Line> {
+        self.hunks.iter().flat_map(|hunk| hunk.lines.iter())
+    }
+
+    /// Content of the file before the change. `None` for added and binary files.
+    pub fn read_old(&self, repo: &Repository) -> Result<Option<String>> {
+        match (self.is_binary, self.old_id) {
+            (false, Some(id)) => read_blob(repo, id),
+            _ => Ok(None),
+        }
+    }
+
+    /// Content of the file after the change. `None` for deleted and binary files.
+    ///
+    /// Blobs of working tree changes are not written to the object database,
+    /// so such files are read from the working directory instead.
+    pub fn read_new(&self, repo: &Repository) -> Result<Option<String>> {
+        if self.is_binary {
+            return Ok(None);
+        }
+        let Some(id) = self.new_id else {
+            return Ok(None);
+        };
+        if repo.find_blob(id).is_ok() {
+            return read_blob(repo, id);
+        }
+
+        match (repo.workdir(), &self.new_path) {
+            (Some(workdir), Some(path)) => {
+                let content = std::fs::read(workdir.join(path))?;
+                Ok(Some(String::from_utf8_lossy(&content).to_string()))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+fn read_blob(repo: &Repository, id: git2::Oid) -> Result<Option<String>> {
+    let blob = repo.find_blob(id)?;
+    match blob.is_binary() {
+        true => Ok(None),
+        false => Ok(Some(String::from_utf8_lossy(blob.content()).to_string())),
+    }
 }
 
+/// Commit in the analysed range.
 #[derive(Debug, Serialize)]
-pub struct FileDelta {
-    pub value: String,
+pub struct CommitInfo {
+    pub sha: String,
+    pub message: String,
+    pub author: Signature,
+    pub committer: Signature,
+    /// Computed on first use, as it takes a diff of the commit's tree.
+    #[serde(skip)]
+    files_changed: OnceLock<usize>,
 }
 
-impl FileDelta {
-    fn from(value: String) -> Self {
-        Self { value }
+#[derive(Debug, Serialize)]
+pub struct Signature {
+    pub name: String,
+    pub email: String,
+}
+
+/// Header of a commit message following <https://www.conventionalcommits.org>,
+/// e.g. `feat(api)!: remove users endpoint`.
+#[derive(Debug)]
+pub struct ConventionalCommit<'a> {
+    pub kind: &'a str,
+    pub scope: Option<&'a str>,
+    pub breaking: bool,
+}
+
+lazy_static! {
+    static ref CONVENTIONAL_HEADER: Regex =
+        Regex::new(r"^(?P<kind>[\w-]+)(?:\((?P<scope>[^()]*)\))?(?P<breaking>!)?: ").unwrap();
+    static ref TRAILER: Regex =
+        Regex::new(r"^(?P<key>BREAKING CHANGE|[\w-]+)(?:: | #)(?P<value>.*)$").unwrap();
+}
+
+impl CommitInfo {
+    /// Number of files changed by the commit, compared against its first parent.
+    pub fn files_changed(&self, repo: &Repository) -> Result<usize, git2::Error> {
+        if let Some(files_changed) = self.files_changed.get() {
+            return Ok(*files_changed);
+        }
+
+        let commit = repo.find_commit(git2::Oid::from_str(&self.sha)?)?;
+        let parent_tree = match commit.parent_count() {
+            0 => None,
+            _ => Some(commit.parent(0)?.tree()?),
+        };
+        let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+
+        Ok(*self.files_changed.get_or_init(|| diff.deltas().len()))
+    }
+
+    /// First line of the commit message.
+    pub fn summary(&self) -> &str {
+        self.message.lines().next().unwrap_or("")
+    }
+
+    /// `None` if the message does not follow conventional commits.
+    pub fn conventional(&self) -> Option<ConventionalCommit<'_>> {
+        let captures = CONVENTIONAL_HEADER.captures(self.summary())?;
+        Some(ConventionalCommit {
+            kind: captures.name("kind")?.as_str(),
+            scope: captures.name("scope").map(|scope| scope.as_str()),
+            breaking: captures.name("breaking").is_some(),
+        })
+    }
+
+    /// Trailers from the last paragraph of the message, e.g. `Reviewed-by: John <john@doe.com>`
+    /// or `BREAKING CHANGE: users endpoint was removed`.
+    /// Indented lines continue the value of the previous trailer. So do unindented ones,
+    /// until the next trailer, as footers of Conventional Commits may span many lines.
+    pub fn trailers(&self) -> Vec<(String, String)> {
+        let paragraphs: Vec<&str> = self.message.trim_end().split("\n\n").collect();
+        // Trailers never follow the header directly
+        let Some(last) = paragraphs.last().filter(|_| paragraphs.len() > 1) else {
+            return vec![];
+        };
+
+        let mut trailers: Vec<(String, String)> = Vec::new();
+        for line in last.lines() {
+            if line.starts_with(char::is_whitespace) {
+                match trailers.last_mut() {
+                    Some((_, value)) => {
+                        value.push(' ');
+                        value.push_str(line.trim());
+                        continue;
+                    }
+                    None => return vec![],
+                }
+            }
+            let Some(captures) = TRAILER.captures(line) else {
+                match trailers.last_mut() {
+                    Some((_, value)) => {
+                        value.push('\n');
+                        value.push_str(line);
+                        continue;
+                    }
+                    // Paragraph, which does not start with a trailer, is a part of the body
+                    None => return vec![],
+                }
+            };
+            trailers.push((
+                captures["key"].to_string(),
+                captures["value"].trim().to_string(),
+            ));
+        }
+
+        trailers
     }
+
+    /// Tells whether the commit is marked as breaking, either with `!` in its header,
+    /// or with `BREAKING CHANGE` trailer.
+    pub fn is_breaking(&self) -> bool {
+        self.conventional()
+            .is_some_and(|conventional| conventional.breaking)
+            || self
+                .trailers()
+                .iter()
+                .any(|(key, _)| key == "BREAKING CHANGE" || key == "BREAKING-CHANGE")
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Added,
+    Modified,
+    Deleted,
+    Renamed,
+    Copied,
+}
+
+/// File modes are kept in git's octal notation, e.g. `100644`.
+#[derive(Debug, Serialize)]
+pub struct ModeChange {
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Hunk {
+    pub header: String,
+    pub old_start: u32,
+    pub old_lines: u32,
+    pub new_start: u32,
+    pub new_lines: u32,
+    pub lines: Vec<Line>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Line {
+    pub origin: LineOrigin,
+    pub old_lineno: Option<u32>,
+    pub new_lineno: Option<u32>,
+    /// Line content, without the trailing newline.
+    pub content: String,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LineOrigin {
+    Added,
+    Removed,
+    Context,
 }
 
 pub enum DiffOptions<'a> {
-    Branches { from: &'a str, to: &'a str },
+    /// Tree of `base` compared directly against the tree of `head`,
+    /// so changes made on `head` are the new side of the diff.
+    Branches { head: &'a str, base: &'a str },
+    /// Changes introduced by a single commit, compared against its first parent.
+    /// Root commits are compared against an empty tree.
+    Commit { id: &'a str },
+    /// Arbitrary revision range, either `A..B` or `A...B`.
+    /// The latter compares `B` against the merge-base of `A` and `B`.
+    /// Omitted end of the range defaults to `HEAD`.
+    Range { spec: &'a str },
+    /// Changes made on `head` since it diverged from `base`.
+    /// Matches the diff GitHub shows for a pull request from `head` into `base`.
+    MergeBase { head: &'a str, base: &'a str },
+    /// Changes staged in the index, compared against `HEAD`.
+    Staged,
+    /// All uncommitted changes in the working tree, including untracked files.
+    WorkingTree,
 }
 
-pub fn extract_difference(repo: &Repository, options: &DiffOptions) -> Result<Diff> {
-    match options {
-        DiffOptions::Branches { from, to } => extract_difference_branches(repo, from, to),
+impl DiffOptions<'_> {
+    /// Names of refs, which have to be present in the repository to compute the diff.
+    pub fn refs(&self) -> Vec<&str> {
+        match self {
+            DiffOptions::Branches { head, base } | DiffOptions::MergeBase { head, base } => {
+                vec![base, head]
+            }
+            DiffOptions::Commit { id } => vec![id],
+            DiffOptions::Range { spec } => match parse_range(spec) {
+                Ok((from, to, _)) => vec![from, to],
+                Err(_) => vec![],
+            },
+            DiffOptions::Staged | DiffOptions::WorkingTree => vec![],
+        }
     }
 }
 
+pub fn extract_difference(
+    repo: &Repository,
+    remote: &str,
+    options: &DiffOptions,
+    renames: &RenamesConfig,
+) -> Result<Diff> {
+    let mut comparison = match options {
+        DiffOptions::Branches { head, base } => diff_branches(repo, remote, head, base)?,
+        DiffOptions::Commit { id } => diff_commit(repo, remote, id)?,
+        DiffOptions::Range { spec } => diff_range(repo, remote, spec)?,
+        DiffOptions::MergeBase { head, base } => diff_merge_base(repo, remote, head, base)?,
+        DiffOptions::Staged => diff_staged(repo)?,
+        DiffOptions::WorkingTree => diff_working_tree(repo)?,
+    };
+
+    if renames.enabled {
+        find_similar(&mut comparison.diff, renames)?;
+    }
+
+    Ok(Diff {
+        files: collect_files(&comparison.diff)?,
+        commits: collect_commits(repo, &comparison.from, &comparison.to)?,
+        from: comparison.from,
+        to: comparison.to,
+    })
+}
+
 pub fn fetch_remote<'a, F>(repo: &Repository, remote_name: &str, credentials: F) -> Result<()>
 where
     F: Fn(&str, Option<&str>, CredentialType) -> Result<Cred, git2::Error> + 'a,
@@ -65,41 +403,643 @@ where
     let mut fetch_options = git2::FetchOptions::new();
     fetch_options.remote_callbacks(callback);
 
-    let refspecs = ["+refs/heads/*:refs/remotes/origin/*"];
+    let refspecs = [format!("+refs/heads/*:refs/remotes/{}/*", remote_name)];
 
     remote.fetch(&refspecs, Some(&mut fetch_options), None)?;
 
     Ok(())
 }
 
-pub fn extract_difference_branches(
+/// Name of the branch, which HEAD of the repository at `url` points to.
+pub fn default_branch<'a, F>(url: &Url, credentials: &F) -> Result<String>
+where
+    F: Fn(&str, Option<&str>, CredentialType) -> Result<Cred, git2::Error> + 'a,
+{
+    let mut remote = git2::Remote::create_detached(url.as_str())?;
+
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(credentials);
+
+    let connection = remote.connect_auth(git2::Direction::Fetch, Some(callbacks), None)?;
+    let head = connection.default_branch()?;
+    let head = head
+        .as_str()
+        .ok_or_else(|| anyhow!("Default branch of {} is not valid UTF-8", url))?;
+
+    Ok(head.strip_prefix("refs/heads/").unwrap_or(head).to_string())
+}
+
+/// Depth of the first clone, when looking for history required for the comparison.
+/// Every following fetch doubles it.
+const INITIAL_FETCH_DEPTH: i32 = 32;
+
+/// Depth, which makes libgit2 fetch the whole remaining history.
+const FETCH_DEPTH_UNSHALLOW: i32 = i32::MAX;
+
+/// Clones repository with history just deep enough to compute the diff described
+/// by `options`, i.e. until both ends of the comparison and their common ancestor,
+/// if needed, are reachable.
+///
+/// Refs used by `options` are resolved right after the first clone, so a misspelled
+/// one fails the run before any more history is fetched. Then, the history of these
+/// refs is deepened in place, doubling the depth with every fetch.
+/// With `max_depth` unset, history is eventually fetched in full.
+pub fn clone_with_history<'a, F>(
+    credentials: &F,
+    url: &Url,
+    clone_into: &Path,
+    remote_name: &str,
+    options: &DiffOptions,
+    max_depth: Option<u32>,
+) -> Result<Repository>
+where
+    F: Fn(&str, Option<&str>, CredentialType) -> Result<Cred, git2::Error> + 'a,
+{
+    let limit = max_depth;
+    let max_depth = max_fetch_depth(limit);
+    let mut depth = INITIAL_FETCH_DEPTH.min(max_depth);
+
+    utils::prepare_directory(clone_into)?;
+    let repo = clone_repo(credentials, url, clone_into, remote_name, depth)?;
+
+    // Clone fetches only branches, and tags pointing within the fetched history
+    for name in options.refs() {
+        if is_ref_name(remote_name, name) && resolve_commit(&repo, remote_name, name).is_err() {
+            let refspec = match name.starts_with("refs/") {
+                true => format!("+{}:{}", name, name),
+                false => tag_refspec(name),
+            };
+            fetch_with_depth(&repo, remote_name, &[refspec], credentials, depth)?;
+        }
+    }
+    resolve_refs(&repo, remote_name, options)?;
+
+    let refspecs: Vec<String> = options
+        .refs()
+        .into_iter()
+        .filter_map(
+            |name| match repo.find_reference(&format!("refs/tags/{}", name)).is_ok() {
+                true => Some(tag_refspec(name)),
+                false => refspec_for(remote_name, name),
+            },
+        )
+        .collect();
+
+    while repo.is_shallow() && !has_required_history(&repo, remote_name, options) {
+        if depth >= max_depth {
+            return Err(history_too_shallow(limit, depth).into());
+        }
+
+        depth = depth.saturating_mul(2).min(max_depth);
+        info!("history is not deep enough, deepening it to {}", depth);
+        fetch_with_depth(&repo, remote_name, &refspecs, credentials, depth)?;
+    }
+
+    trace!("history is deep enough at depth {}", depth);
+    Ok(repo)
+}
+
+/// Makes sure every ref used by `options`, which names a ref rather than a revision,
+/// resolves to a commit. Revisions, like `HEAD~1` or a SHA, may only become reachable
+/// once enough history is fetched.
+fn resolve_refs(
     repo: &Repository,
-    from_branch: &str,
-    to_branch: &str,
-) -> Result<Diff> {
-    // TODO: Those refs values most likely should not be hardcoded
-    let ref_from = repo.find_reference(&format!("refs/remotes/origin/{}", from_branch))?;
-    let ref_to = repo.find_reference(&format!("refs/remotes/origin/{}", to_branch))?;
+    remote_name: &str,
+    options: &DiffOptions,
+) -> Result<(), GitError> {
+    for name in options.refs() {
+        if is_ref_name(remote_name, name) {
+            resolve_commit(repo, remote_name, name)?;
+        }
+    }
 
-    let commit_a = ref_from.peel_to_commit()?;
-    let commit_b = ref_to.peel_to_commit()?;
+    Ok(())
+}
 
-    let tree_a = commit_a.tree()?;
-    let tree_b = commit_b.tree()?;
+fn tag_refspec(name: &str) -> String {
+    format!("+refs/tags/{}:refs/tags/{}", name, name)
+}
 
-    let diff = repo.diff_tree_to_tree(Some(&tree_a), Some(&tree_b), None)?;
-    let mut diff_output = Vec::new();
-    diff.print(git2::DiffFormat::Patch, |_delta, _hunk, line| {
-        diff_output.extend_from_slice(line.content());
-        true
-    })?;
+/// Whether `name` names a ref, like a branch or a tag, rather than a revision,
+/// like `HEAD~1` or an abbreviated SHA.
+fn is_ref_name(remote_name: &str, name: &str) -> bool {
+    let is_sha = name.len() >= 7 && name.chars().all(|c| c.is_ascii_hexdigit());
+    refspec_for(remote_name, name).is_some() && !is_sha
+}
+
+/// Makes sure an existing shallow repository contains history needed to compute
+/// the diff described by `options`.
+///
+/// Refs used by `options` are fetched once, with history deepened straight to
+/// `max_depth`, or unshallowed if it is unset.
+pub fn deepen_history<'a, F>(
+    repo: &Repository,
+    remote_name: &str,
+    options: &DiffOptions,
+    credentials: &F,
+    max_depth: Option<u32>,
+) -> Result<()>
+where
+    F: Fn(&str, Option<&str>, CredentialType) -> Result<Cred, git2::Error> + 'a,
+{
+    if !repo.is_shallow() || has_required_history(repo, remote_name, options) {
+        trace!("repository contains history required for the comparison");
+        return Ok(());
+    }
 
-    let diff_str = str::from_utf8(&diff_output)
-        .map_err(|e| git2::Error::from_str(&format!("UTF-8 conversion error: {}", e)))?
-        .to_string();
+    let refspecs: Vec<String> = options
+        .refs()
+        .into_iter()
+        .filter_map(|name| refspec_for(remote_name, name))
+        .collect();
 
-    Ok(Diff {
-        deltas: vec![FileDelta::from(diff_str)],
+    let depth = max_fetch_depth(max_depth);
+    info!("deepening shallow repository history to depth {}", depth);
+    fetch_with_depth(repo, remote_name, &refspecs, credentials, depth)?;
+
+    if repo.is_shallow() && !has_required_history(repo, remote_name, options) {
+        return Err(history_too_shallow(max_depth, depth).into());
+    }
+
+    Ok(())
+}
+
+/// Error for history, which lacks commits required for the comparison after fetching
+/// it at `depth`, or in full, if `max_depth` is unset.
+fn history_too_shallow(max_depth: Option<u32>, depth: i32) -> GitError {
+    match max_depth {
+        Some(_) => {
+            error!("history is not deep enough at max depth {}", depth);
+            GitError::HistoryTooShallow { depth }
+        }
+        None => {
+            error!("history is not deep enough, even though it was fetched in full");
+            GitError::HistoryNotFound
+        }
+    }
+}
+
+fn max_fetch_depth(max_depth: Option<u32>) -> i32 {
+    match max_depth {
+        Some(depth) => i32::try_from(depth).unwrap_or(FETCH_DEPTH_UNSHALLOW),
+        None => FETCH_DEPTH_UNSHALLOW,
+    }
+}
+
+fn fetch_with_depth<'a, F>(
+    repo: &Repository,
+    remote_name: &str,
+    refspecs: &[String],
+    credentials: &F,
+    depth: i32,
+) -> Result<()>
+where
+    F: Fn(&str, Option<&str>, CredentialType) -> Result<Cred, git2::Error> + 'a,
+{
+    let mut remote = repo.find_remote(remote_name)?;
+
+    let fetch_options = || {
+        let mut callbacks = RemoteCallbacks::new();
+        callbacks.credentials(credentials);
+
+        let mut fetch_options = git2::FetchOptions::new();
+        fetch_options.remote_callbacks(callbacks);
+        fetch_options.depth(depth);
+        fetch_options
+    };
+
+    if let Err(err) = remote.fetch(refspecs, Some(&mut fetch_options()), None) {
+        // Tags and raw SHAs may not exist as remote branches, so fall back
+        // to refspecs configured for the remote
+        info!(
+            "failed to fetch requested refs, fetching default refspecs. Error: {}",
+            err
+        );
+        remote.fetch::<&str>(&[], Some(&mut fetch_options()), None)?;
+    }
+
+    Ok(())
+}
+
+/// Maps a ref name, as passed by the user, to a refspec fetching it from the remote.
+/// Returns `None` for names, which can not be fetched directly, like `HEAD~1`.
+fn refspec_for(remote_name: &str, name: &str) -> Option<String> {
+    if name.starts_with("HEAD") || name.contains(['~', '^', ':', '@']) {
+        return None;
+    }
+
+    match name.starts_with("refs/") {
+        true => Some(format!("+{}:{}", name, name)),
+        false => Some(format!(
+            "+refs/heads/{}:refs/remotes/{}/{}",
+            name, remote_name, name
+        )),
+    }
+}
+
+fn has_required_history(repo: &Repository, remote: &str, options: &DiffOptions) -> bool {
+    let has_merge_base = |from: &str, to: &str| match (
+        resolve_commit(repo, remote, from),
+        resolve_commit(repo, remote, to),
+    ) {
+        (Ok(from), Ok(to)) => repo.merge_base(from.id(), to.id()).is_ok(),
+        _ => false,
+    };
+    let has_commits = |from: &str, to: &str| {
+        resolve_commit(repo, remote, from).is_ok() && resolve_commit(repo, remote, to).is_ok()
+    };
+
+    match options {
+        DiffOptions::Branches { head, base } => has_commits(base, head),
+        DiffOptions::MergeBase { head, base } => has_merge_base(base, head),
+        DiffOptions::Range { spec } => match parse_range(spec) {
+            Ok((from, to, true)) => has_merge_base(from, to),
+            Ok((from, to, false)) => has_commits(from, to),
+            Err(_) => true,
+        },
+        DiffOptions::Commit { id } => match resolve_commit(repo, remote, id) {
+            // Parents of commits on the shallow boundary are not known yet
+            Ok(commit) => !shallow_commits(repo).contains(&commit.id()),
+            Err(_) => false,
+        },
+        DiffOptions::Staged | DiffOptions::WorkingTree => true,
+    }
+}
+
+/// Commits on the boundary of a shallow repository, as listed in `.git/shallow`.
+fn shallow_commits(repo: &Repository) -> Vec<git2::Oid> {
+    std::fs::read_to_string(repo.path().join("shallow"))
+        .map(|content| {
+            content
+                .lines()
+                .filter_map(|line| git2::Oid::from_str(line.trim()).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Resolves `name` to a commit, trying in order:
+/// - remote-tracking branch of `remote`
+/// - local branch
+/// - tag
+/// - full refname, e.g. `refs/pull/1/head`
+/// - any revision understood by git, e.g. a raw SHA or `HEAD~1`
+pub fn resolve_commit<'r>(
+    repo: &'r Repository,
+    remote: &str,
+    name: &str,
+) -> Result<Commit<'r>, GitError> {
+    let candidates = [
+        format!("refs/remotes/{}/{}", remote, name),
+        format!("refs/heads/{}", name),
+        format!("refs/tags/{}", name),
+        name.to_string(),
+    ];
+
+    for candidate in &candidates {
+        if let Ok(reference) = repo.find_reference(candidate) {
+            if let Ok(commit) = reference.peel_to_commit() {
+                trace!("resolved {} as {}", name, candidate);
+                return Ok(commit);
+            }
+        }
+    }
+
+    match repo
+        .revparse_single(name)
+        .and_then(|object| object.peel_to_commit())
+    {
+        Ok(commit) => {
+            trace!("resolved {} as revision {}", name, commit.id());
+            Ok(commit)
+        }
+        Err(err) => {
+            error!("failed to resolve {}", name);
+            Err(GitError::UnresolvedRef {
+                name: name.to_string(),
+                candidates: candidates.to_vec(),
+                err,
+            })
+        }
+    }
+}
+
+/// Raw diff along with both of the compared revisions.
+struct Comparison<'r> {
+    diff: git2::Diff<'r>,
+    from: Revision,
+    to: Revision,
+}
+
+fn diff_branches<'r>(
+    repo: &'r Repository,
+    remote: &str,
+    head_branch: &str,
+    base_branch: &str,
+) -> Result<Comparison<'r>> {
+    let head = resolve_commit(repo, remote, head_branch)?;
+    let base = resolve_commit(repo, remote, base_branch)?;
+
+    Ok(Comparison {
+        diff: repo.diff_tree_to_tree(Some(&base.tree()?), Some(&head.tree()?), None)?,
+        from: Revision::commit(base_branch, &base),
+        to: Revision::commit(head_branch, &head),
+    })
+}
+
+fn diff_commit<'r>(repo: &'r Repository, remote: &str, id: &str) -> Result<Comparison<'r>> {
+    let commit = resolve_commit(repo, remote, id)?;
+    let tree = commit.tree()?;
+
+    let (parent_tree, from) = match commit.parent_count() {
+        0 => (None, Revision::named("empty tree")),
+        _ => {
+            let parent = commit.parent(0)?;
+            (
+                Some(parent.tree()?),
+                Revision::commit(&format!("{}^", id), &parent),
+            )
+        }
+    };
+
+    Ok(Comparison {
+        diff: repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)?,
+        from,
+        to: Revision::commit(id, &commit),
+    })
+}
+
+/// Splits `A..B` or `A...B` range into its ends, and tells whether it is the latter.
+fn parse_range(spec: &str) -> Result<(&str, &str, bool)> {
+    let (from, to, is_symmetric) = match spec.split_once("...") {
+        Some((from, to)) => (from, to, true),
+        None => match spec.split_once("..") {
+            Some((from, to)) => (from, to, false),
+            None => return Err(anyhow!("Range {} must be in form of A..B or A...B", spec)),
+        },
+    };
+
+    // Same as in git, omitted end of the range defaults to HEAD
+    let from = if from.is_empty() { "HEAD" } else { from };
+    let to = if to.is_empty() { "HEAD" } else { to };
+
+    Ok((from, to, is_symmetric))
+}
+
+fn diff_range<'r>(repo: &'r Repository, remote: &str, spec: &str) -> Result<Comparison<'r>> {
+    let (from_name, to_name, is_symmetric) = parse_range(spec)?;
+
+    let from = resolve_commit(repo, remote, from_name)?;
+    let to = resolve_commit(repo, remote, to_name)?;
+
+    let (from, from_revision) = match is_symmetric {
+        true => {
+            let base = repo.find_commit(repo.merge_base(from.id(), to.id())?)?;
+            let name = format!("merge-base of {} and {}", from_name, to_name);
+            let revision = Revision::commit(&name, &base);
+            (base, revision)
+        }
+        false => {
+            let revision = Revision::commit(from_name, &from);
+            (from, revision)
+        }
+    };
+
+    Ok(Comparison {
+        diff: repo.diff_tree_to_tree(Some(&from.tree()?), Some(&to.tree()?), None)?,
+        from: from_revision,
+        to: Revision::commit(to_name, &to),
+    })
+}
+
+fn diff_merge_base<'r>(
+    repo: &'r Repository,
+    remote: &str,
+    head_branch: &str,
+    base_branch: &str,
+) -> Result<Comparison<'r>> {
+    let head = resolve_commit(repo, remote, head_branch)?;
+    let target = resolve_commit(repo, remote, base_branch)?;
+
+    let base = repo.find_commit(repo.merge_base(head.id(), target.id())?)?;
+    let base_name = format!("merge-base of {} and {}", head_branch, base_branch);
+
+    Ok(Comparison {
+        diff: repo.diff_tree_to_tree(Some(&base.tree()?), Some(&head.tree()?), None)?,
+        from: Revision::commit(&base_name, &base),
+        to: Revision::commit(head_branch, &head),
+    })
+}
+
+fn diff_staged(repo: &Repository) -> Result<Comparison<'_>> {
+    let head = head_commit(repo)?;
+    let head_tree = head.as_ref().map(|commit| commit.tree()).transpose()?;
+
+    Ok(Comparison {
+        diff: repo.diff_tree_to_index(head_tree.as_ref(), None, None)?,
+        from: head_revision(head.as_ref()),
+        to: Revision::named("index"),
+    })
+}
+
+fn diff_working_tree(repo: &Repository) -> Result<Comparison<'_>> {
+    let head = head_commit(repo)?;
+    let head_tree = head.as_ref().map(|commit| commit.tree()).transpose()?;
+
+    let mut options = git2::DiffOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .show_untracked_content(true);
+
+    Ok(Comparison {
+        diff: repo.diff_tree_to_workdir_with_index(head_tree.as_ref(), Some(&mut options))?,
+        from: head_revision(head.as_ref()),
+        to: Revision::named("working tree"),
+    })
+}
+
+/// Returns the current `HEAD` commit, or `None` if repository has no commits yet.
+fn head_commit(repo: &Repository) -> Result<Option<Commit<'_>>> {
+    match repo.head() {
+        Ok(head) => Ok(Some(head.peel_to_commit()?)),
+        Err(err) if err.code() == git2::ErrorCode::UnbornBranch => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn head_revision(head: Option<&Commit>) -> Revision {
+    match head {
+        Some(commit) => Revision::commit("HEAD", commit),
+        None => Revision::named("empty tree"),
+    }
+}
+
+/// Collects commits reachable from `to`, but not from `from`, newest first.
+///
+/// In a shallow repository, the walk stops at the merge-base of `from` and `to`, or at
+/// the shallow boundary, if the merge-base is not fetched. Commits on the boundary are
+/// left out, as their parents, and so the changes they introduce, are unknown.
+fn collect_commits(repo: &Repository, from: &Revision, to: &Revision) -> Result<Vec<CommitInfo>> {
+    let Some(to_sha) = &to.sha else {
+        return Ok(vec![]);
+    };
+    let to_id = git2::Oid::from_str(to_sha)?;
+
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+    walk.push(to_id)?;
+    if let Some(from_sha) = &from.sha {
+        let from_id = git2::Oid::from_str(from_sha)?;
+        walk.hide(from_id)?;
+        if repo.is_shallow() && repo.merge_base(from_id, to_id).is_err() {
+            warn!(
+                "merge-base of {} and {} is not fetched, commits are collected up to the shallow boundary",
+                from.name, to.name
+            );
+        }
+    }
+
+    let boundary = shallow_commits(repo);
+    let mut commits = Vec::new();
+    for id in walk {
+        let id = id?;
+        if boundary.contains(&id) {
+            trace!("skipping commit {} on the shallow boundary", id);
+            continue;
+        }
+        commits.push(collect_commit(&repo.find_commit(id)?));
+    }
+    trace!(
+        "Collected {} commit(s) of the analysed range",
+        commits.len()
+    );
+
+    Ok(commits)
+}
+
+fn collect_commit(commit: &Commit) -> CommitInfo {
+    CommitInfo {
+        sha: commit.id().to_string(),
+        message: String::from_utf8_lossy(commit.message_bytes()).to_string(),
+        author: Signature::from(&commit.author()),
+        committer: Signature::from(&commit.committer()),
+        files_changed: OnceLock::new(),
+    }
+}
+
+impl From<&git2::Signature<'_>> for Signature {
+    fn from(signature: &git2::Signature) -> Self {
+        Self {
+            name: String::from_utf8_lossy(signature.name_bytes()).to_string(),
+            email: String::from_utf8_lossy(signature.email_bytes()).to_string(),
+        }
+    }
+}
+
+/// Pairs deleted and added files into renames and copies,
+/// so a moved file is not reported as removed.
+fn find_similar(diff: &mut git2::Diff, renames: &RenamesConfig) -> Result<()> {
+    let mut options = git2::DiffFindOptions::new();
+    options
+        .renames(true)
+        .renames_from_rewrites(true)
+        .for_untracked(true)
+        .rename_threshold(renames.similarity_threshold)
+        .copies(renames.copies)
+        .copy_threshold(renames.similarity_threshold);
+
+    diff.find_similar(Some(&mut options))?;
+    Ok(())
+}
+
+fn collect_files(diff: &git2::Diff) -> Result<Vec<FileDiff>> {
+    let mut files = Vec::with_capacity(diff.deltas().len());
+
+    for idx in 0..diff.deltas().len() {
+        // Patch is not generated for unchanged files
+        if let Some(patch) = git2::Patch::from_diff(diff, idx)? {
+            files.push(collect_file_diff(&patch)?);
+        }
+    }
+
+    Ok(files)
+}
+
+fn collect_file_diff(patch: &git2::Patch) -> Result<FileDiff> {
+    let delta = patch.delta();
+    let old_file = delta.old_file();
+    let new_file = delta.new_file();
+
+    let status = match delta.status() {
+        git2::Delta::Added | git2::Delta::Untracked => FileStatus::Added,
+        git2::Delta::Deleted => FileStatus::Deleted,
+        git2::Delta::Renamed => FileStatus::Renamed,
+        git2::Delta::Copied => FileStatus::Copied,
+        _ => FileStatus::Modified,
+    };
+
+    let mode_change = match (old_file.exists(), new_file.exists()) {
+        (true, true) if old_file.mode() != new_file.mode() => Some(ModeChange {
+            old: format!("{:o}", u32::from(old_file.mode())),
+            new: format!("{:o}", u32::from(new_file.mode())),
+        }),
+        _ => None,
+    };
+
+    let mut hunks = Vec::with_capacity(patch.num_hunks());
+    for hunk_idx in 0..patch.num_hunks() {
+        let (hunk, lines_count) = patch.hunk(hunk_idx)?;
+
+        let mut lines = Vec::with_capacity(lines_count);
+        for line_idx in 0..lines_count {
+            let line = patch.line_in_hunk(hunk_idx, line_idx)?;
+            let origin = match line.origin_value() {
+                git2::DiffLineType::Addition => LineOrigin::Added,
+                git2::DiffLineType::Deletion => LineOrigin::Removed,
+                git2::DiffLineType::Context => LineOrigin::Context,
+                // "No newline at end of file" markers carry no content
+                _ => continue,
+            };
+
+            let content = String::from_utf8_lossy(line.content());
+            lines.push(Line {
+                origin,
+                old_lineno: line.old_lineno(),
+                new_lineno: line.new_lineno(),
+                content: content.strip_suffix('\n').unwrap_or(&content).to_string(),
+            });
+        }
+
+        hunks.push(Hunk {
+            header: String::from_utf8_lossy(hunk.header())
+                .trim_end()
+                .to_string(),
+            old_start: hunk.old_start(),
+            old_lines: hunk.old_lines(),
+            new_start: hunk.new_start(),
+            new_lines: hunk.new_lines(),
+            lines,
+        });
+    }
+
+    Ok(FileDiff {
+        old_path: old_file
+            .exists()
+            .then(|| old_file.path())
+            .flatten()
+            .map(PathBuf::from),
+        new_path: new_file
+            .exists()
+            .then(|| new_file.path())
+            .flatten()
+            .map(PathBuf::from),
+        status,
+        is_binary: delta.flags().is_binary(),
+        mode_change,
+        hunks,
+        old_id: old_file.exists().then(|| old_file.id()),
+        new_id: new_file.exists().then(|| new_file.id()),
     })
 }
 
@@ -125,6 +1065,8 @@ pub fn clone_repo<'a, F>(
     credentials: F,
     url: &Url,
     clone_into: &Path,
+    remote_name: &str,
+    depth: i32,
 ) -> Result<Repository, GitError>
 where
     F: Fn(&str, Option<&str>, CredentialType) -> Result<Cred, git2::Error> + 'a,
@@ -139,9 +1081,10 @@ where
 
     let mut fetch_options = git2::FetchOptions::new();
     fetch_options.remote_callbacks(callbacks);
-    fetch_options.depth(1);
+    fetch_options.depth(depth);
 
     builder.fetch_options(fetch_options);
+    builder.remote_create(|repo, _, url| repo.remote(remote_name, url));
 
     match builder.clone(url.as_str(), clone_into) {
         Ok(repository) => Ok(repository),
@@ -159,3 +1102,345 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit(message: &str) -> CommitInfo {
+        let signature = || Signature {
+            name: "John".to_string(),
+            email: "john@doe.com".to_string(),
+        };
+        CommitInfo {
+            sha: String::new(),
+            message: message.to_string(),
+            author: signature(),
+            committer: signature(),
+            files_changed: OnceLock::new(),
+        }
+    }
+
+    fn trailers(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn conventional_parses_header() {
+        let cases = [
+            ("feat: add users", Some(("feat", None, false))),
+            (
+                "fix(api): handle empty body",
+                Some(("fix", Some("api"), false)),
+            ),
+            (
+                "feat(api)!: remove users",
+                Some(("feat", Some("api"), true)),
+            ),
+            ("chore!: drop node 16", Some(("chore", None, true))),
+            ("build-deps: bump git2", Some(("build-deps", None, false))),
+            ("feat(): empty scope", Some(("feat", Some(""), false))),
+            ("Add users", None),
+            ("feat:missing space", None),
+            ("feat(a(b)): nested scope", None),
+            ("\nfeat: header after empty line", None),
+        ];
+
+        for (message, expected) in cases {
+            let commit = commit(message);
+            let conventional = commit
+                .conventional()
+                .map(|conventional| (conventional.kind, conventional.scope, conventional.breaking));
+            assert_eq!(conventional, expected, "parsing `{}`", message);
+        }
+    }
+
+    #[test]
+    fn trailers_are_read_from_the_last_paragraph() {
+        let message = "feat: add users\n\nBody of the commit.\n\n\
+            Reviewed-by: Jane <jane@doe.com>\nRefs #123\n";
+        assert_eq!(
+            commit(message).trailers(),
+            trailers(&[("Reviewed-by", "Jane <jane@doe.com>"), ("Refs", "123")])
+        );
+    }
+
+    #[test]
+    fn trailers_continue_on_indented_lines() {
+        let message =
+            "feat: add users\n\nBREAKING CHANGE: users endpoint\n  was removed\n\tfor good\n\
+            Signed-off-by: Jane <jane@doe.com>";
+        assert_eq!(
+            commit(message).trailers(),
+            trailers(&[
+                ("BREAKING CHANGE", "users endpoint was removed for good"),
+                ("Signed-off-by", "Jane <jane@doe.com>"),
+            ])
+        );
+    }
+
+    #[test]
+    fn trailers_continue_on_unindented_lines_until_next_trailer() {
+        let message = "feat: add users\n\nBREAKING CHANGE: users endpoint\nwas removed\n\
+            Reviewed-by: Jane <jane@doe.com>\nand John";
+        assert_eq!(
+            commit(message).trailers(),
+            trailers(&[
+                ("BREAKING CHANGE", "users endpoint\nwas removed"),
+                ("Reviewed-by", "Jane <jane@doe.com>\nand John"),
+            ])
+        );
+    }
+
+    #[test]
+    fn trailers_are_empty_for_body_paragraph() {
+        let cases = [
+            // Only the header
+            "Reviewed-by: Jane <jane@doe.com>",
+            // Paragraph, which does not start with a trailer, is a part of the body
+            "feat: add users\n\nSome description\nReviewed-by: Jane <jane@doe.com>",
+            "feat: add users\n\nThis adds users: all of them.",
+            // Continuation without a trailer to continue
+            "feat: add users\n\n  indented\nReviewed-by: Jane <jane@doe.com>",
+        ];
+
+        for message in cases {
+            assert!(
+                commit(message).trailers().is_empty(),
+                "trailers of `{}`",
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn is_breaking_by_header_or_trailer() {
+        let cases = [
+            ("feat(api)!: remove users", true),
+            (
+                "feat: remove users\n\nBREAKING CHANGE: users endpoint was removed",
+                true,
+            ),
+            (
+                "feat: remove users\n\nBREAKING-CHANGE: users endpoint was removed",
+                true,
+            ),
+            (
+                "feat: remove users\n\nbreaking change: lowercase is not a trailer key",
+                false,
+            ),
+            (
+                "feat: remove users\n\nBREAKING CHANGE: body\nwith more text",
+                true,
+            ),
+            // Mentioned in the body, rather than as a trailer
+            (
+                "feat: remove users\n\nThis is a\nBREAKING CHANGE: for all clients",
+                false,
+            ),
+            ("feat: add users", false),
+            ("Remove users", false),
+        ];
+
+        for (message, expected) in cases {
+            assert_eq!(commit(message).is_breaking(), expected, "`{}`", message);
+        }
+    }
+
+    #[test]
+    fn default_branch_is_read_from_remote_head() {
+        let dir = std::env::temp_dir().join(format!("impactifier-{}", uuid::Uuid::new_v4()));
+        let repo = Repository::init(&dir).unwrap();
+        let signature = git2::Signature::now("John", "john@doe.com").unwrap();
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        repo.commit(
+            Some("refs/heads/trunk"),
+            &signature,
+            &signature,
+            "initial",
+            &tree,
+            &[],
+        )
+        .unwrap();
+        repo.set_head("refs/heads/trunk").unwrap();
+
+        let url = Url::from_directory_path(&dir).unwrap();
+        let credentials = |_: &str, _: Option<&str>, _: CredentialType| Cred::default();
+        let branch = default_branch(&url, &credentials);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(branch.unwrap(), "trunk");
+    }
+
+    /// Commits a chain of empty commits named after `messages` on top of `parent`.
+    fn commit_chain(
+        repo: &Repository,
+        parent: Option<git2::Oid>,
+        messages: &[&str],
+    ) -> Vec<git2::Oid> {
+        let signature = git2::Signature::now("John", "john@doe.com").unwrap();
+        let tree = repo
+            .find_tree(repo.treebuilder(None).unwrap().write().unwrap())
+            .unwrap();
+
+        let mut parent = parent;
+        messages
+            .iter()
+            .map(|message| {
+                let parents: Vec<Commit> = parent
+                    .map(|id| repo.find_commit(id).unwrap())
+                    .into_iter()
+                    .collect();
+                let parents: Vec<&Commit> = parents.iter().collect();
+                let id = repo
+                    .commit(None, &signature, &signature, message, &tree, &parents)
+                    .unwrap();
+                parent = Some(id);
+                id
+            })
+            .collect()
+    }
+
+    fn messages(commits: &[CommitInfo]) -> Vec<&str> {
+        commits
+            .iter()
+            .map(|commit| commit.message.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn collect_commits_stops_at_merge_base_or_shallow_boundary() {
+        let dir = std::env::temp_dir().join(format!("impactifier-{}", uuid::Uuid::new_v4()));
+        let repo = Repository::init(&dir).unwrap();
+        let shared = commit_chain(&repo, None, &["s1", "s2", "s3"]);
+        let base = commit_chain(&repo, Some(shared[2]), &["b1", "b2", "b3", "b4"]);
+        let head = commit_chain(&repo, Some(shared[2]), &["h1"]);
+        let revision = |id: &git2::Oid| Revision::commit("", &repo.find_commit(*id).unwrap());
+
+        let commits = collect_commits(&repo, &revision(&base[3]), &revision(&head[0])).unwrap();
+        assert_eq!(messages(&commits), vec!["h1"]);
+
+        // History of base is cut before it reaches the merge-base
+        std::fs::write(
+            dir.join(".git/shallow"),
+            format!("{}\n{}\n", base[2], shared[1]),
+        )
+        .unwrap();
+        let repo = Repository::open(&dir).unwrap();
+        let commits = collect_commits(&repo, &revision(&base[3]), &revision(&head[0])).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // `s2` is on the boundary, so its changes are unknown
+        assert_eq!(messages(&commits), vec!["h1", "s3"]);
+    }
+
+    /// `git daemon` serving repositories from `dir`. libgit2 fetches shallow history
+    /// only over smart protocols, which the local transport does not use.
+    struct GitDaemon {
+        process: std::process::Child,
+        port: u16,
+    }
+
+    impl GitDaemon {
+        fn serve(dir: &Path) -> Self {
+            let port = std::net::TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port();
+            // `git daemon` would run the daemon as its child, which outlives the test when killed
+            let exec_path = std::process::Command::new("git")
+                .arg("--exec-path")
+                .output()
+                .unwrap()
+                .stdout;
+            let exec_path = PathBuf::from(String::from_utf8(exec_path).unwrap().trim());
+            let process = std::process::Command::new(exec_path.join("git-daemon"))
+                .arg("--export-all")
+                .arg("--listen=127.0.0.1")
+                .arg(format!("--port={}", port))
+                .arg(format!("--base-path={}", dir.display()))
+                .arg(dir)
+                .stderr(std::process::Stdio::null())
+                .spawn()
+                .unwrap();
+
+            for _ in 0..100 {
+                if std::net::TcpStream::connect(("127.0.0.1", port)).is_ok() {
+                    break;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            }
+            GitDaemon { process, port }
+        }
+
+        fn url(&self, name: &str) -> Url {
+            Url::parse(&format!("git://127.0.0.1:{}/{}", self.port, name)).unwrap()
+        }
+    }
+
+    impl Drop for GitDaemon {
+        fn drop(&mut self) {
+            let _ = self.process.kill();
+            let _ = self.process.wait();
+        }
+    }
+
+    #[test]
+    fn clone_with_history_deepens_until_merge_base_is_reached() {
+        let dir = std::env::temp_dir().join(format!("impactifier-{}", uuid::Uuid::new_v4()));
+        let origin = Repository::init(dir.join("origin")).unwrap();
+        // Merge-base is 41 commits deep on both branches, so it is reached at depth 64
+        let chain = |parent, name: &str, length| {
+            let messages: Vec<String> = (0..length).map(|i| format!("{} {}", name, i)).collect();
+            let messages: Vec<&str> = messages.iter().map(String::as_str).collect();
+            commit_chain(&origin, parent, &messages)
+        };
+        let shared = chain(None, "shared", 50);
+        let main = chain(shared.last().copied(), "main", 40);
+        let feature = chain(shared.last().copied(), "feature", 40);
+        origin
+            .reference("refs/heads/main", *main.last().unwrap(), true, "")
+            .unwrap();
+        origin
+            .reference("refs/heads/feature", *feature.last().unwrap(), true, "")
+            .unwrap();
+        origin.set_head("refs/heads/main").unwrap();
+
+        let daemon = GitDaemon::serve(&dir);
+        let credentials = |_: &str, _: Option<&str>, _: CredentialType| Cred::default();
+        let options = DiffOptions::MergeBase {
+            head: "feature",
+            base: "main",
+        };
+        let clone = |into: &str, max_depth| {
+            clone_with_history(
+                &credentials,
+                &daemon.url("origin"),
+                &dir.join(into),
+                "origin",
+                &options,
+                max_depth,
+            )
+        };
+
+        let repo = clone("deepened", None).unwrap();
+        assert!(repo.is_shallow());
+        assert!(has_required_history(&repo, "origin", &options));
+        assert!(repo.find_commit(shared[0]).is_err());
+
+        let err = clone("limited", Some(40)).err().unwrap();
+        drop(daemon);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert!(matches!(
+            err.downcast::<GitError>().unwrap(),
+            GitError::HistoryTooShallow { depth: 40 }
+        ));
+    }
+}
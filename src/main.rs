@@ -1,12 +1,19 @@
 mod cli;
 mod config;
+mod engine;
 mod git;
+mod report;
 mod transform;
 mod utils;
 
-use anyhow::Result;
-use cli::CliError;
+use std::process::ExitCode;
 
-fn main() -> Result<(), CliError> {
-    cli::run()
+fn main() -> ExitCode {
+    match cli::run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {}", err.describe());
+            ExitCode::from(err.exit_code())
+        }
+    }
 }
@@ -0,0 +1,137 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tracing::{info, trace};
+use uuid::Uuid;
+
+use crate::config::Rule;
+use crate::engine::Finding;
+use crate::git::{Diff, Revision};
+
+/// Result of a single impactifier run, written as JSON to the path given by `--output`.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub run: RunMetadata,
+    /// `None` if run failed before the diff was extracted.
+    pub comparison: Option<Comparison>,
+    pub rules: Vec<RuleReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunMetadata {
+    pub id: String,
+    pub version: String,
+    /// Seconds since the Unix epoch.
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    pub status: RunStatus,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Running,
+    Success,
+    Failure,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Comparison {
+    pub from: Revision,
+    pub to: Revision,
+    pub files_changed: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RuleReport {
+    pub name: String,
+    pub findings: Vec<Finding>,
+}
+
+impl Report {
+    pub fn new() -> Self {
+        Self {
+            run: RunMetadata {
+                id: Uuid::new_v4().to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                started_at: now(),
+                finished_at: None,
+                status: RunStatus::Running,
+                error: None,
+            },
+            comparison: None,
+            rules: Vec::new(),
+        }
+    }
+
+    pub fn set_comparison(&mut self, diff: &Diff) {
+        self.comparison = Some(Comparison {
+            from: diff.from.clone(),
+            to: diff.to.clone(),
+            files_changed: diff.files.len(),
+        });
+    }
+
+    /// Groups findings by the rule which produced them.
+    /// Rules without any findings are reported as well.
+    pub fn set_findings(&mut self, rules: &[Rule], findings: Vec<Finding>) {
+        self.rules = rules
+            .iter()
+            .map(|rule| RuleReport {
+                name: rule.name.clone(),
+                findings: Vec::new(),
+            })
+            .collect();
+
+        for finding in findings {
+            if let Some(rule_report) = self
+                .rules
+                .iter_mut()
+                .find(|rule_report| rule_report.name == finding.rule)
+            {
+                rule_report.findings.push(finding);
+            }
+        }
+    }
+
+    pub fn findings(&self) -> impl Iterator<Item = &Finding> {
+        self.rules
+            .iter()
+            .flat_map(|rule_report| &rule_report.findings)
+    }
+
+    pub fn finish(&mut self, error: Option<String>) {
+        self.run.finished_at = Some(now());
+        self.run.status = match error {
+            Some(_) => RunStatus::Failure,
+            None => RunStatus::Success,
+        };
+        self.run.error = error;
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        trace!("Saving report to {:?}", path);
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.flush()?;
+        info!("Report saved to {:?}", path);
+        Ok(())
+    }
+}
+
+impl Default for Report {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
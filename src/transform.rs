@@ -1,9 +1,14 @@
-use crate::config::CustomStep;
+use crate::config::{CustomStep, ScriptLimitsConfig, StepCondition, TransformStep};
 use anyhow::Result;
-use rhai::{Dynamic, Engine, Map, Scope};
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::sync::Mutex;
+use regex::Regex;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Module, Scope, AST};
+use serde::Serialize;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
 use tracing::trace;
 
 #[derive(Debug, Clone)]
@@ -15,17 +20,234 @@ pub struct Context {
     pub class_name: Option<String>,
 }
 
-#[allow(dead_code)]
 pub trait TransformFn {
     fn execute(
         &self,
         context: &Context,
         args: Option<&serde_yaml::Value>,
-    ) -> Result<String, String>;
+    ) -> Result<String, ExecutionError>;
+
+    /// Candidate values produced from the matched string.
+    /// Transforms, which map one value into many, override it.
+    fn execute_all(
+        &self,
+        context: &Context,
+        args: Option<&serde_yaml::Value>,
+    ) -> Result<Vec<String>, ExecutionError> {
+        self.execute(context, args).map(|value| vec![value])
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ExecutionError {
+    #[error("{}", msg)]
+    Failure { msg: String },
+    /// Custom script exceeded one of `ScriptLimitsConfig` limits.
+    #[error("Script exceeded {} limit", limit)]
+    LimitExceeded { limit: String },
+}
+
+impl From<String> for ExecutionError {
+    fn from(msg: String) -> Self {
+        ExecutionError::Failure { msg }
+    }
 }
 
 lazy_static::lazy_static! {
     static ref TRANSFORM_REGISTRY: Mutex<HashMap<String, Box<dyn TransformFn + Send + Sync>>> = Mutex::new(HashMap::new());
+    /// Compiled patterns used by transforms, so they are not recompiled every time one runs.
+    static ref REGEX_CACHE: Mutex<HashMap<String, Regex>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Error, Debug)]
+pub enum TransformError {
+    #[error("Transform {} is not registered", name)]
+    UnknownTransform { name: String },
+    #[error("Transform {} failed. Error: {}", name, err)]
+    ExecutionFailure { name: String, err: ExecutionError },
+    #[error(
+        "Script of custom transform {} failed to compile. Error: {}",
+        name,
+        err
+    )]
+    CompilationFailure { name: String, err: String },
+    #[error(
+        "Script of custom transform {} does not define function {}",
+        name,
+        function
+    )]
+    MissingEntryFunction { name: String, function: String },
+    /// `index` is position of the failed step in `Transform.steps`, starting from 0.
+    #[error("Transform step at index {} failed. {}", index, err)]
+    StepFailure {
+        index: usize,
+        err: Box<TransformError>,
+    },
+}
+
+/// Values produced by a single step of the pipeline, from one of its input values.
+#[derive(Debug, Clone, Serialize)]
+pub struct StepOutput {
+    pub step: String,
+    pub input: String,
+    pub values: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct PipelineOutput {
+    /// Candidate values, without duplicates, in order in which they were produced.
+    pub values: Vec<String>,
+    /// Intermediate values, in order in which steps were run.
+    pub steps: Vec<StepOutput>,
+}
+
+/// Runs transform steps in order, feeding every value produced by a step into the next one,
+/// as `matched_string` of the context.
+pub fn run_pipeline(
+    steps: &[TransformStep],
+    context: Context,
+) -> Result<PipelineOutput, TransformError> {
+    let mut outputs = Vec::with_capacity(steps.len());
+    let mut values = vec![context.matched_string.clone()];
+
+    for (index, step) in steps.iter().enumerate() {
+        values = run_step(step, &context, values, &mut outputs).map_err(|err| {
+            TransformError::StepFailure {
+                index,
+                err: Box::new(err),
+            }
+        })?;
+    }
+
+    Ok(PipelineOutput {
+        values,
+        steps: outputs,
+    })
+}
+
+fn run_step(
+    step: &TransformStep,
+    context: &Context,
+    values: Vec<String>,
+    outputs: &mut Vec<StepOutput>,
+) -> Result<Vec<String>, TransformError> {
+    let mut results = Vec::new();
+
+    for value in values {
+        let context = Context {
+            matched_string: value,
+            ..context.clone()
+        };
+
+        match step {
+            TransformStep::Run(step) => {
+                if let Some(condition) = &step.when {
+                    if !condition_holds(condition, &context) {
+                        trace!(
+                            "Skipping step {}, as its condition does not hold",
+                            step.name
+                        );
+                        results.push(context.matched_string);
+                        continue;
+                    }
+                }
+
+                let step_values = execute_transform(&step.name, &context, step.args.as_ref())?;
+                trace!(
+                    "Step {} transformed `{}` into {:?}",
+                    step.name,
+                    context.matched_string,
+                    step_values
+                );
+
+                outputs.push(StepOutput {
+                    step: step.name.clone(),
+                    input: context.matched_string,
+                    values: step_values.clone(),
+                });
+                results.extend(step_values);
+            }
+            TransformStep::Branch(branch) => {
+                let steps = match condition_holds(&branch.condition, &context) {
+                    true => &branch.then,
+                    false => &branch.otherwise,
+                };
+
+                let mut branch_values = vec![context.matched_string.clone()];
+                for step in steps {
+                    branch_values = run_step(step, &context, branch_values, outputs)?;
+                }
+                results.extend(branch_values);
+            }
+        }
+    }
+
+    Ok(deduplicate(results))
+}
+
+fn deduplicate(values: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    values
+        .into_iter()
+        .filter(|value| seen.insert(value.clone()))
+        .collect()
+}
+
+fn condition_holds(condition: &StepCondition, context: &Context) -> bool {
+    let path_matches = condition
+        .path
+        .as_ref()
+        .is_none_or(|glob| glob.is_match(&context.file_path));
+    let extension_matches = condition.extension.as_ref().is_none_or(|extension| {
+        context
+            .file_path
+            .extension()
+            .is_some_and(|ext| ext == extension.trim_start_matches('.'))
+    });
+    let class_name_matches = condition
+        .class_name
+        .as_ref()
+        .is_none_or(|class_name| context.class_name.as_ref() == Some(class_name));
+    let value_matches = condition
+        .matches
+        .as_ref()
+        .is_none_or(|regex| regex.is_match(&context.matched_string));
+
+    path_matches && extension_matches && class_name_matches && value_matches
+}
+
+impl TransformError {
+    /// Name of the step and the limit, if the error was caused by a script exceeding it.
+    pub fn exceeded_limit(&self) -> Option<(&str, &str)> {
+        match self {
+            TransformError::StepFailure { err, .. } => err.exceeded_limit(),
+            TransformError::ExecutionFailure {
+                name,
+                err: ExecutionError::LimitExceeded { limit },
+            } => Some((name, limit)),
+            _ => None,
+        }
+    }
+}
+
+/// Looks up transform registered under `name` and executes it.
+pub fn execute_transform(
+    name: &str,
+    context: &Context,
+    args: Option<&serde_yaml::Value>,
+) -> Result<Vec<String>, TransformError> {
+    let registry = TRANSFORM_REGISTRY.lock().unwrap();
+    let func = registry
+        .get(name)
+        .ok_or_else(|| TransformError::UnknownTransform {
+            name: name.to_string(),
+        })?;
+
+    func.execute_all(context, args)
+        .map_err(|err| TransformError::ExecutionFailure {
+            name: name.to_string(),
+            err,
+        })
 }
 
 fn register_transform(name: &str, func: Box<dyn TransformFn + Send + Sync>) {
@@ -35,24 +257,71 @@ fn register_transform(name: &str, func: Box<dyn TransformFn + Send + Sync>) {
         .insert(name.to_string(), func);
 }
 
-pub fn init_registry(custom_steps: Option<Vec<CustomStep>>) {
+/// Transforms available without any configuration.
+fn standard_transforms() -> Vec<(&'static str, Box<dyn TransformFn + Send + Sync>)> {
+    vec![
+        ("toLowerCase", Box::new(ToLowerCase)),
+        ("toUpperCase", Box::new(ToUpperCase)),
+        ("prepend", Box::new(Prepend)),
+        ("append", Box::new(Append)),
+        ("trim", Box::new(Trim)),
+        ("substring", Box::new(Substring)),
+        ("split", Box::new(Split)),
+        ("join", Box::new(Join)),
+        ("toCamelCase", Box::new(ToCamelCase)),
+        ("toSnakeCase", Box::new(ToSnakeCase)),
+        ("toKebabCase", Box::new(ToKebabCase)),
+        ("toPascalCase", Box::new(ToPascalCase)),
+        ("replace", Box::new(Replace)),
+        ("expand", Box::new(Expand)),
+        ("caseVariants", Box::new(CaseVariants)),
+    ]
+}
+
+/// Whether `name` is taken by one of the standard transforms.
+pub fn is_standard_transform(name: &str) -> bool {
+    standard_transforms()
+        .iter()
+        .any(|(standard, _)| *standard == name)
+}
+
+/// Registers standard transforms, and compiles scripts of the custom ones.
+pub fn init_registry(
+    custom_steps: Option<Vec<CustomStep>>,
+    limits: &ScriptLimitsConfig,
+) -> Result<(), TransformError> {
     trace!("Starting to register transform scripts functions");
 
-    register_transform("toUpperCase", Box::new(ToLowerCase));
-    register_transform("replace", Box::new(Replace));
+    for (name, func) in standard_transforms() {
+        register_transform(name, func);
+    }
     trace!("Standard functions registered");
 
     if let Some(steps) = custom_steps {
+        // Engine is shared, as creating one registers all of Rhai's standard packages
+        let engine = Arc::new(script_engine(limits));
         for step in steps {
             trace!("Initializing custom function {}", &step.name);
+            let ast =
+                engine
+                    .compile(&step.script)
+                    .map_err(|e| TransformError::CompilationFailure {
+                        name: step.name.clone(),
+                        err: e.to_string(),
+                    })?;
+            let entry = entry_function(&step, &ast)?;
             register_transform(
                 &step.name,
                 Box::new(CustomFunction {
-                    script: step.script.to_string(),
+                    engine: Arc::clone(&engine),
+                    ast,
+                    entry,
                 }),
             );
         }
     }
+
+    Ok(())
 }
 
 pub struct ToLowerCase;
@@ -62,11 +331,379 @@ impl TransformFn for ToLowerCase {
         &self,
         context: &Context,
         _args: Option<&serde_yaml::Value>,
-    ) -> Result<String, String> {
+    ) -> Result<String, ExecutionError> {
         Ok(context.matched_string.to_lowercase())
     }
 }
 
+pub struct ToUpperCase;
+
+impl TransformFn for ToUpperCase {
+    fn execute(
+        &self,
+        context: &Context,
+        _args: Option<&serde_yaml::Value>,
+    ) -> Result<String, ExecutionError> {
+        Ok(context.matched_string.to_uppercase())
+    }
+}
+
+/// Adds `value` argument before the matched string.
+pub struct Prepend;
+
+impl TransformFn for Prepend {
+    fn execute(
+        &self,
+        context: &Context,
+        args: Option<&serde_yaml::Value>,
+    ) -> Result<String, ExecutionError> {
+        let value = required_str(args, "value")?;
+        Ok(format!("{}{}", value, context.matched_string))
+    }
+}
+
+/// Adds `value` argument after the matched string.
+pub struct Append;
+
+impl TransformFn for Append {
+    fn execute(
+        &self,
+        context: &Context,
+        args: Option<&serde_yaml::Value>,
+    ) -> Result<String, ExecutionError> {
+        let value = required_str(args, "value")?;
+        Ok(format!("{}{}", context.matched_string, value))
+    }
+}
+
+/// Removes leading and trailing whitespace, or any of `chars`, if specified.
+pub struct Trim;
+
+impl TransformFn for Trim {
+    fn execute(
+        &self,
+        context: &Context,
+        args: Option<&serde_yaml::Value>,
+    ) -> Result<String, ExecutionError> {
+        let value = &context.matched_string;
+        match optional_str(args, "chars")? {
+            Some(chars) => Ok(value.trim_matches(|c: char| chars.contains(c)).to_string()),
+            None => Ok(value.trim().to_string()),
+        }
+    }
+}
+
+/// Takes characters from `start` (inclusive, defaults to 0) to `end` (exclusive, defaults
+/// to the end of the string). Negative indices count from the end of the string.
+/// Indices out of bounds are clamped to the length of the string.
+pub struct Substring;
+
+impl TransformFn for Substring {
+    fn execute(
+        &self,
+        context: &Context,
+        args: Option<&serde_yaml::Value>,
+    ) -> Result<String, ExecutionError> {
+        let chars: Vec<char> = context.matched_string.chars().collect();
+        let start = optional_int(args, "start")?.unwrap_or(0);
+        let end = optional_int(args, "end")?.unwrap_or(chars.len() as i64);
+
+        let start = clamp_index(start, chars.len());
+        let end = clamp_index(end, chars.len());
+        if start > end {
+            return Err(format!(
+                "start resolves to position {}, which is after end position {} in `{}`",
+                start, end, context.matched_string
+            )
+            .into());
+        }
+
+        Ok(chars[start..end].iter().collect())
+    }
+}
+
+/// Splits the matched string by `separator`, and takes part at `index` (defaults to 0).
+/// Negative index counts from the last part.
+pub struct Split;
+
+impl TransformFn for Split {
+    fn execute(
+        &self,
+        context: &Context,
+        args: Option<&serde_yaml::Value>,
+    ) -> Result<String, ExecutionError> {
+        let separator = required_str(args, "separator")?;
+        if separator.is_empty() {
+            return Err("Argument 'separator' must not be empty".to_string().into());
+        }
+        let index = optional_int(args, "index")?.unwrap_or(0);
+
+        let parts: Vec<&str> = context.matched_string.split(separator).collect();
+        let position = match index < 0 {
+            true => parts.len() as i64 + index,
+            false => index,
+        };
+
+        usize::try_from(position)
+            .ok()
+            .and_then(|position| parts.get(position))
+            .map(|part| part.to_string())
+            .ok_or_else(|| {
+                format!(
+                    "Index {} is out of bounds, `{}` has {} part(s) separated by `{}`",
+                    index,
+                    context.matched_string,
+                    parts.len(),
+                    separator
+                )
+                .into()
+            })
+    }
+}
+
+/// Splits the matched string into words, and joins them with `separator`,
+/// keeping their case, e.g. `GetUserHandler` joined with `/` is `Get/User/Handler`.
+pub struct Join;
+
+impl TransformFn for Join {
+    fn execute(
+        &self,
+        context: &Context,
+        args: Option<&serde_yaml::Value>,
+    ) -> Result<String, ExecutionError> {
+        let separator = required_str(args, "separator")?;
+        Ok(split_words(&context.matched_string).join(separator))
+    }
+}
+
+pub struct ToCamelCase;
+
+impl TransformFn for ToCamelCase {
+    fn execute(
+        &self,
+        context: &Context,
+        _args: Option<&serde_yaml::Value>,
+    ) -> Result<String, ExecutionError> {
+        Ok(to_camel_case(&context.matched_string))
+    }
+}
+
+pub struct ToPascalCase;
+
+impl TransformFn for ToPascalCase {
+    fn execute(
+        &self,
+        context: &Context,
+        _args: Option<&serde_yaml::Value>,
+    ) -> Result<String, ExecutionError> {
+        Ok(to_pascal_case(&context.matched_string))
+    }
+}
+
+pub struct ToSnakeCase;
+
+impl TransformFn for ToSnakeCase {
+    fn execute(
+        &self,
+        context: &Context,
+        _args: Option<&serde_yaml::Value>,
+    ) -> Result<String, ExecutionError> {
+        Ok(to_snake_case(&context.matched_string))
+    }
+}
+
+pub struct ToKebabCase;
+
+impl TransformFn for ToKebabCase {
+    fn execute(
+        &self,
+        context: &Context,
+        _args: Option<&serde_yaml::Value>,
+    ) -> Result<String, ExecutionError> {
+        Ok(to_kebab_case(&context.matched_string))
+    }
+}
+
+fn to_camel_case(value: &str) -> String {
+    let mut result = String::new();
+    for (idx, word) in split_words(value).iter().enumerate() {
+        match idx {
+            0 => result.push_str(&word.to_lowercase()),
+            _ => result.push_str(&capitalize(word)),
+        }
+    }
+    result
+}
+
+fn to_pascal_case(value: &str) -> String {
+    split_words(value)
+        .iter()
+        .map(|word| capitalize(word))
+        .collect()
+}
+
+fn to_snake_case(value: &str) -> String {
+    split_words(value)
+        .iter()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<String>>()
+        .join("_")
+}
+
+fn to_kebab_case(value: &str) -> String {
+    split_words(value)
+        .iter()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<String>>()
+        .join("-")
+}
+
+/// Splits a string into words on non-alphanumeric characters and case boundaries.
+/// Acronyms are kept together, e.g. `HTTPServer_v2` is split into `HTTP`, `Server`, `v2`.
+fn split_words(value: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+
+    let chars: Vec<char> = value.chars().collect();
+    for (idx, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+
+        if let Some(prev) = word.chars().last() {
+            let next = chars.get(idx + 1);
+            let is_boundary = c.is_uppercase()
+                && (prev.is_lowercase()
+                    || prev.is_numeric()
+                    || (prev.is_uppercase() && next.is_some_and(|next| next.is_lowercase())));
+            if is_boundary {
+                words.push(std::mem::take(&mut word));
+            }
+        }
+        word.push(c);
+    }
+
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect(),
+        None => String::new(),
+    }
+}
+
+/// Number of patterns kept in `REGEX_CACHE`.
+const REGEX_CACHE_CAPACITY: usize = 256;
+
+fn cached_regex(pattern: &str) -> Result<Regex, String> {
+    let mut cache = REGEX_CACHE.lock().unwrap();
+    if let Some(regex) = cache.get(pattern) {
+        return Ok(regex.clone());
+    }
+
+    let regex =
+        Regex::new(pattern).map_err(|e| format!("Invalid pattern `{}`. Error: {}", pattern, e))?;
+    // Scripts may build patterns from matched values, so the cache is bounded
+    if cache.len() >= REGEX_CACHE_CAPACITY {
+        if let Some(evicted) = cache.keys().next().cloned() {
+            cache.remove(&evicted);
+        }
+    }
+    cache.insert(pattern.to_string(), regex.clone());
+    Ok(regex)
+}
+
+/// Resolves possibly negative index into position within `0..=len`.
+fn clamp_index(index: i64, len: usize) -> usize {
+    let len = len as i64;
+    let position = match index < 0 {
+        true => len + index,
+        false => index,
+    };
+    position.clamp(0, len) as usize
+}
+
+fn optional_arg<'a>(
+    args: Option<&'a serde_yaml::Value>,
+    name: &str,
+) -> Result<Option<&'a serde_yaml::Value>, String> {
+    match args {
+        Some(serde_yaml::Value::Mapping(mapping)) => Ok(mapping.get(name)),
+        Some(serde_yaml::Value::Null) | None => Ok(None),
+        Some(_) => Err("Arguments must be a mapping".to_string()),
+    }
+}
+
+fn optional_str<'a>(
+    args: Option<&'a serde_yaml::Value>,
+    name: &str,
+) -> Result<Option<&'a str>, String> {
+    match optional_arg(args, name)? {
+        Some(value) => value
+            .as_str()
+            .map(Some)
+            .ok_or_else(|| format!("Argument '{}' must be a string", name)),
+        None => Ok(None),
+    }
+}
+
+fn required_str<'a>(args: Option<&'a serde_yaml::Value>, name: &str) -> Result<&'a str, String> {
+    optional_str(args, name)?.ok_or_else(|| format!("Missing required argument '{}'", name))
+}
+
+fn required_str_list<'a>(
+    args: Option<&'a serde_yaml::Value>,
+    name: &str,
+) -> Result<Vec<&'a str>, String> {
+    let value =
+        optional_arg(args, name)?.ok_or_else(|| format!("Missing required argument '{}'", name))?;
+    let list = value
+        .as_sequence()
+        .ok_or_else(|| format!("Argument '{}' must be a list of strings", name))?;
+
+    list.iter()
+        .map(|item| {
+            item.as_str()
+                .ok_or_else(|| format!("Argument '{}' must be a list of strings", name))
+        })
+        .collect()
+}
+
+fn optional_bool(args: Option<&serde_yaml::Value>, name: &str) -> Result<Option<bool>, String> {
+    match optional_arg(args, name)? {
+        Some(value) => value
+            .as_bool()
+            .map(Some)
+            .ok_or_else(|| format!("Argument '{}' must be a boolean", name)),
+        None => Ok(None),
+    }
+}
+
+fn optional_int(args: Option<&serde_yaml::Value>, name: &str) -> Result<Option<i64>, String> {
+    match optional_arg(args, name)? {
+        Some(value) => value
+            .as_i64()
+            .map(Some)
+            .ok_or_else(|| format!("Argument '{}' must be an integer", name)),
+        None => Ok(None),
+    }
+}
+
+/// Replaces matches of `pattern` regex with `with`, which may reference capture groups
+/// as `$1` or `${name}`. With `literal: true`, both are treated as plain strings instead.
+/// `count` limits number of replacements, all matches are replaced if it is 0 or unspecified.
 pub struct Replace;
 
 impl TransformFn for Replace {
@@ -74,29 +711,296 @@ impl TransformFn for Replace {
         &self,
         context: &Context,
         args: Option<&serde_yaml::Value>,
-    ) -> Result<String, String> {
-        if let Some(args) = args {
-            let pattern = args.get("pattern").and_then(|v| v.as_str()).unwrap_or("");
-            let with = args.get("with").and_then(|v| v.as_str()).unwrap_or("");
-            Ok(context.matched_string.replace(pattern, with))
-        } else {
-            Err("Replace requires 'pattern' and 'with' arguments".to_string())
+    ) -> Result<String, ExecutionError> {
+        let pattern = required_str(args, "pattern")?;
+        let with = required_str(args, "with")?;
+        let literal = optional_bool(args, "literal")?.unwrap_or(false);
+        let count = match optional_int(args, "count")? {
+            Some(count) => usize::try_from(count)
+                .map_err(|_| "Argument 'count' must not be negative".to_string())?,
+            // `replacen` treats 0 as no limit
+            None => 0,
+        };
+
+        let value = &context.matched_string;
+        match literal {
+            true => match count {
+                0 => Ok(value.replace(pattern, with)),
+                _ => Ok(value.replacen(pattern, with, count)),
+            },
+            false => Ok(cached_regex(pattern)?
+                .replacen(value, count, with)
+                .to_string()),
         }
     }
 }
 
+/// Placeholder in `Expand` templates, replaced with the matched string.
+const VALUE_PLACEHOLDER: &str = "$value";
+
+/// Produces a value for each of `templates`, in which `$value` is replaced with
+/// the matched string, e.g. `/v1/$value` and `/v2/$value`.
+pub struct Expand;
+
+impl TransformFn for Expand {
+    fn execute(
+        &self,
+        context: &Context,
+        args: Option<&serde_yaml::Value>,
+    ) -> Result<String, ExecutionError> {
+        first_value(self.execute_all(context, args)?)
+    }
+
+    fn execute_all(
+        &self,
+        context: &Context,
+        args: Option<&serde_yaml::Value>,
+    ) -> Result<Vec<String>, ExecutionError> {
+        let templates = required_str_list(args, "templates")?;
+        Ok(templates
+            .iter()
+            .map(|template| template.replace(VALUE_PLACEHOLDER, &context.matched_string))
+            .collect())
+    }
+}
+
+/// Produces the matched string in each of `cases`, which defaults to
+/// `camel`, `pascal`, `snake` and `kebab`. `lower` and `upper` are supported as well.
+pub struct CaseVariants;
+
+impl TransformFn for CaseVariants {
+    fn execute(
+        &self,
+        context: &Context,
+        args: Option<&serde_yaml::Value>,
+    ) -> Result<String, ExecutionError> {
+        first_value(self.execute_all(context, args)?)
+    }
+
+    fn execute_all(
+        &self,
+        context: &Context,
+        args: Option<&serde_yaml::Value>,
+    ) -> Result<Vec<String>, ExecutionError> {
+        let cases = match optional_arg(args, "cases")? {
+            Some(_) => required_str_list(args, "cases")?,
+            None => vec!["camel", "pascal", "snake", "kebab"],
+        };
+
+        let value = &context.matched_string;
+        let mut values = Vec::with_capacity(cases.len());
+        for case in cases {
+            values.push(match case {
+                "camel" => to_camel_case(value),
+                "pascal" => to_pascal_case(value),
+                "snake" => to_snake_case(value),
+                "kebab" => to_kebab_case(value),
+                "lower" => value.to_lowercase(),
+                "upper" => value.to_uppercase(),
+                _ => return Err(format!("Unknown case `{}`", case).into()),
+            });
+        }
+
+        Ok(deduplicate(values))
+    }
+}
+
+fn first_value(values: Vec<String>) -> Result<String, ExecutionError> {
+    values
+        .into_iter()
+        .next()
+        .ok_or_else(|| "Transform produced no values".to_string().into())
+}
+
+thread_local! {
+    /// Start of the currently running script call, checked against `timeout_ms` limit.
+    static SCRIPT_STARTED_AT: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// How often, in number of operations, script is checked for exceeding `timeout_ms`.
+const TIMEOUT_CHECK_INTERVAL: u64 = 1024;
+
+/// Namespace, under which `helper_module` is available to scripts,
+/// e.g. `impactifier::to_snake_case(context.matched_string)`.
+const HELPER_MODULE: &str = "impactifier";
+
+/// Rhai forbids any function call with 0 call levels, while 0 disables the limit in the config.
+fn max_call_levels(max_call_depth: usize) -> usize {
+    match max_call_depth {
+        0 => usize::MAX,
+        depth => depth,
+    }
+}
+
+fn script_engine(limits: &ScriptLimitsConfig) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .register_static_module(HELPER_MODULE, helper_module().into())
+        .set_max_operations(limits.max_operations)
+        .set_max_call_levels(max_call_levels(limits.max_call_depth))
+        .set_max_string_size(limits.max_string_size)
+        .set_max_array_size(limits.max_array_size)
+        .set_max_map_size(limits.max_map_size);
+
+    if limits.timeout_ms > 0 {
+        let timeout = Duration::from_millis(limits.timeout_ms);
+        engine.on_progress(move |operations| {
+            if operations % TIMEOUT_CHECK_INTERVAL != 0 {
+                return None;
+            }
+            SCRIPT_STARTED_AT
+                .get()
+                .filter(|started_at| started_at.elapsed() > timeout)
+                .map(|_| Dynamic::from("timeout"))
+        });
+    }
+
+    engine
+}
+
+/// Functions of the standard transforms, regex and path helpers exposed to scripts.
+fn helper_module() -> Module {
+    let mut module = Module::new();
+
+    module.set_native_fn("to_lower_case", |value: &str| Ok(value.to_lowercase()));
+    module.set_native_fn("to_upper_case", |value: &str| Ok(value.to_uppercase()));
+    module.set_native_fn("to_camel_case", |value: &str| Ok(to_camel_case(value)));
+    module.set_native_fn("to_pascal_case", |value: &str| Ok(to_pascal_case(value)));
+    module.set_native_fn("to_snake_case", |value: &str| Ok(to_snake_case(value)));
+    module.set_native_fn("to_kebab_case", |value: &str| Ok(to_kebab_case(value)));
+
+    module.set_native_fn("regex_replace", |value: &str, pattern: &str, with: &str| {
+        Ok(script_regex(pattern)?.replace_all(value, with).to_string())
+    });
+    module.set_native_fn("is_match", |value: &str, pattern: &str| {
+        Ok(script_regex(pattern)?.is_match(value))
+    });
+    // Array of the whole match followed by capture groups, or `()` if nothing matched.
+    // Groups, which did not participate in the match, are `()` as well.
+    module.set_native_fn("captures", |value: &str, pattern: &str| {
+        let regex = script_regex(pattern)?;
+        Ok(match regex.captures(value) {
+            Some(captures) => captures
+                .iter()
+                .map(|group| match group {
+                    Some(group) => Dynamic::from(group.as_str().to_string()),
+                    None => Dynamic::UNIT,
+                })
+                .collect::<Array>()
+                .into(),
+            None => Dynamic::UNIT,
+        })
+    });
+
+    module.set_native_fn("basename", |path: &str| {
+        Ok(path_part(Path::new(path).file_name()))
+    });
+    module.set_native_fn("file_stem", |path: &str| {
+        Ok(path_part(Path::new(path).file_stem()))
+    });
+    module.set_native_fn("extension", |path: &str| {
+        Ok(path_part(Path::new(path).extension()))
+    });
+    module.set_native_fn("parent", |path: &str| {
+        Ok(Path::new(path)
+            .parent()
+            .map(|parent| parent.to_string_lossy().to_string())
+            .unwrap_or_default())
+    });
+
+    module
+}
+
+fn script_regex(pattern: &str) -> Result<Regex, Box<EvalAltResult>> {
+    cached_regex(pattern).map_err(|err| err.into())
+}
+
+fn path_part(part: Option<&std::ffi::OsStr>) -> String {
+    part.map(|part| part.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Name of the `ScriptLimitsConfig` limit, which made the script fail, if any.
+fn exceeded_limit(err: &EvalAltResult) -> Option<&'static str> {
+    match err {
+        EvalAltResult::ErrorTooManyOperations(_) => Some("max_operations"),
+        EvalAltResult::ErrorStackOverflow(_) => Some("max_call_depth"),
+        EvalAltResult::ErrorTerminated(_, _) => Some("timeout_ms"),
+        EvalAltResult::ErrorDataTooLarge(data, _) => {
+            let data = data.to_lowercase();
+            if data.contains("string") {
+                Some("max_string_size")
+            } else if data.contains("array") {
+                Some("max_array_size")
+            } else {
+                Some("max_map_size")
+            }
+        }
+        EvalAltResult::ErrorInFunctionCall(_, _, err, _)
+        | EvalAltResult::ErrorInModule(_, err, _) => exceeded_limit(err),
+        _ => None,
+    }
+}
+
+/// Default function called by custom transforms.
+const ENTRY_FUNCTION: &str = "transform";
+
+/// Step args, which configure the custom transform itself, and are not passed to the script.
+const RESERVED_ARGS: [&str; 3] = ["script", "file", "function"];
+
+/// Transform defined by a Rhai script, compiled once when the registry is initialized.
+///
+/// If the script defines the entry function, it is called with the `context` map,
+/// and - if it takes two parameters - map of the step's args.
+/// Otherwise, the whole script is evaluated with `context` in scope.
 pub struct CustomFunction {
-    pub script: String,
+    engine: Arc<Engine>,
+    ast: AST,
+    entry: Option<EntryFunction>,
+}
+
+struct EntryFunction {
+    name: String,
+    takes_args: bool,
+}
+
+/// Finds function, which should be called by the custom step. It must exist,
+/// if it was explicitly specified.
+fn entry_function(step: &CustomStep, ast: &AST) -> Result<Option<EntryFunction>, TransformError> {
+    let name = step.function.as_deref().unwrap_or(ENTRY_FUNCTION);
+    let function = ast
+        .iter_functions()
+        .filter(|function| function.name == name)
+        .max_by_key(|function| function.params.len());
+
+    match function {
+        Some(function) if function.params.len() <= 2 => Ok(Some(EntryFunction {
+            name: name.to_string(),
+            takes_args: function.params.len() == 2,
+        })),
+        _ if step.function.is_none() => Ok(None),
+        _ => Err(TransformError::MissingEntryFunction {
+            name: step.name.clone(),
+            function: name.to_string(),
+        }),
+    }
 }
 
 impl TransformFn for CustomFunction {
     fn execute(
         &self,
         context: &Context,
-        _args: Option<&serde_yaml::Value>,
-    ) -> Result<String, String> {
-        let engine = Engine::new();
+        args: Option<&serde_yaml::Value>,
+    ) -> Result<String, ExecutionError> {
+        first_value(self.execute_all(context, args)?)
+    }
 
+    /// Script may return either a string, or an array of strings.
+    fn execute_all(
+        &self,
+        context: &Context,
+        args: Option<&serde_yaml::Value>,
+    ) -> Result<Vec<String>, ExecutionError> {
         let mut context_map = Map::new();
         context_map.insert(
             "matched_string".into(),
@@ -111,14 +1015,476 @@ impl TransformFn for CustomFunction {
         }
 
         let mut scope = Scope::new();
-        scope.push("context", context_map);
+        SCRIPT_STARTED_AT.set(Some(Instant::now()));
+        let result = match &self.entry {
+            Some(entry) => match entry.takes_args {
+                true => self.engine.call_fn::<Dynamic>(
+                    &mut scope,
+                    &self.ast,
+                    &entry.name,
+                    (context_map, script_args(args)?),
+                ),
+                false => self.engine.call_fn::<Dynamic>(
+                    &mut scope,
+                    &self.ast,
+                    &entry.name,
+                    (context_map,),
+                ),
+            },
+            None => {
+                scope.push("context", context_map);
+                self.engine
+                    .eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast)
+            }
+        };
+        SCRIPT_STARTED_AT.set(None);
+
+        let result = result.map_err(|e| match exceeded_limit(&e) {
+            Some(limit) => ExecutionError::LimitExceeded {
+                limit: limit.to_string(),
+            },
+            None => format!("Script error: {}", e).into(),
+        })?;
+
+        let not_string = || "Script did not return a string or an array of strings".to_string();
+        match result.is_array() {
+            true => result
+                .into_array()
+                .map_err(|_| not_string())?
+                .into_iter()
+                .map(|value| value.try_cast::<String>().ok_or_else(not_string))
+                .collect::<Result<Vec<String>, String>>()
+                .map_err(ExecutionError::from),
+            false => result
+                .try_cast::<String>()
+                .map(|value| vec![value])
+                .ok_or_else(|| not_string().into()),
+        }
+    }
+}
+
+/// Converts step's args into a Rhai map, leaving out the reserved ones.
+fn script_args(args: Option<&serde_yaml::Value>) -> Result<Map, String> {
+    let mut map = Map::new();
+    let Some(serde_yaml::Value::Mapping(mapping)) = args else {
+        return Ok(map);
+    };
+
+    for (key, value) in mapping {
+        let Some(key) = key.as_str() else {
+            return Err("Argument names must be strings".to_string());
+        };
+        if RESERVED_ARGS.contains(&key) {
+            continue;
+        }
 
-        let result: Dynamic = engine
-            .eval_with_scope::<Dynamic>(&mut scope, &self.script)
-            .map_err(|e| format!("Script error: {}", e))?;
+        let value = rhai::serde::to_dynamic(value).map_err(|e| {
+            format!(
+                "Argument '{}' can not be passed to script. Error: {}",
+                key, e
+            )
+        })?;
+        map.insert(key.into(), value);
+    }
+
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(value: &str) -> Context {
+        Context {
+            matched_string: value.to_string(),
+            file_path: PathBuf::new(),
+            class_name: None,
+        }
+    }
+
+    fn execute(func: &dyn TransformFn, value: &str, args: &str) -> Result<String, String> {
+        let args: serde_yaml::Value = serde_yaml::from_str(args).unwrap();
+        func.execute(&context(value), Some(&args))
+            .map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn split_words_splits_on_separators_and_case_boundaries() {
+        let cases: &[(&str, &[&str])] = &[
+            ("", &[]),
+            ("get", &["get"]),
+            ("getUser", &["get", "User"]),
+            ("GetUserHandler", &["Get", "User", "Handler"]),
+            ("get_user-id", &["get", "user", "id"]),
+            ("  get  user  ", &["get", "user"]),
+            ("HTTPServer_v2", &["HTTP", "Server", "v2"]),
+            ("parseHTTP", &["parse", "HTTP"]),
+            ("userID2Name", &["user", "ID2", "Name"]),
+            ("v2Api", &["v2", "Api"]),
+        ];
+
+        for (value, expected) in cases {
+            assert_eq!(split_words(value), *expected, "splitting `{}`", value);
+        }
+    }
+
+    #[test]
+    fn case_conversions() {
+        let cases = [
+            (
+                "GetUserHandler",
+                "getUserHandler",
+                "GetUserHandler",
+                "get_user_handler",
+                "get-user-handler",
+            ),
+            ("get_user", "getUser", "GetUser", "get_user", "get-user"),
+            (
+                "HTTPServer_v2",
+                "httpServerV2",
+                "HttpServerV2",
+                "http_server_v2",
+                "http-server-v2",
+            ),
+            ("", "", "", "", ""),
+        ];
+
+        for (value, camel, pascal, snake, kebab) in cases {
+            let convert = |func: &dyn TransformFn| execute(func, value, "{}").unwrap();
+            assert_eq!(convert(&ToCamelCase), camel, "camel case of `{}`", value);
+            assert_eq!(convert(&ToPascalCase), pascal, "pascal case of `{}`", value);
+            assert_eq!(convert(&ToSnakeCase), snake, "snake case of `{}`", value);
+            assert_eq!(convert(&ToKebabCase), kebab, "kebab case of `{}`", value);
+        }
+    }
+
+    #[test]
+    fn substring_resolves_negative_and_clamps_out_of_bounds_indices() {
+        let cases = [
+            ("{}", "GetUser"),
+            ("{start: 3}", "User"),
+            ("{end: 3}", "Get"),
+            ("{start: 1, end: 3}", "et"),
+            ("{start: -4}", "User"),
+            ("{start: 0, end: -4}", "Get"),
+            ("{start: -100, end: 100}", "GetUser"),
+            ("{start: 100}", ""),
+            ("{start: 3, end: 3}", ""),
+        ];
+
+        for (args, expected) in cases {
+            assert_eq!(
+                execute(&Substring, "GetUser", args).as_deref(),
+                Ok(expected),
+                "substring with {}",
+                args
+            );
+        }
+    }
+
+    #[test]
+    fn substring_errors() {
+        let cases = [
+            ("{start: 5, end: 2}", "after end position"),
+            ("{start: -1, end: 1}", "after end position"),
+            ("{start: one}", "'start' must be an integer"),
+            ("[1, 2]", "must be a mapping"),
+        ];
+
+        for (args, expected) in cases {
+            let err = execute(&Substring, "GetUser", args).unwrap_err();
+            assert!(err.contains(expected), "substring with {}: {}", args, err);
+        }
+    }
+
+    #[test]
+    fn split_takes_part_at_index() {
+        let cases = [
+            ("{separator: /}", "api"),
+            ("{separator: /, index: 1}", "v1"),
+            ("{separator: /, index: -1}", "users"),
+            ("{separator: /, index: -3}", "api"),
+            ("{separator: '::'}", "api/v1/users"),
+        ];
+
+        for (args, expected) in cases {
+            assert_eq!(
+                execute(&Split, "api/v1/users", args).as_deref(),
+                Ok(expected),
+                "split with {}",
+                args
+            );
+        }
+    }
+
+    #[test]
+    fn split_errors() {
+        let cases = [
+            ("{}", "Missing required argument 'separator'"),
+            ("{separator: ''}", "'separator' must not be empty"),
+            ("{separator: 1}", "'separator' must be a string"),
+            ("{separator: /, index: 3}", "Index 3 is out of bounds"),
+            ("{separator: /, index: -4}", "Index -4 is out of bounds"),
+            ("{separator: /, index: first}", "'index' must be an integer"),
+        ];
+
+        for (args, expected) in cases {
+            let err = execute(&Split, "api/v1/users", args).unwrap_err();
+            assert!(err.contains(expected), "split with {}: {}", args, err);
+        }
+    }
+
+    #[test]
+    fn trim_removes_whitespace_or_given_chars() {
+        let cases = [
+            ("  GetUser \t", "{}", "GetUser"),
+            ("__GetUser_", "{chars: _}", "GetUser"),
+            ("/-api-/", "{chars: /-}", "api"),
+            ("  GetUser  ", "{chars: _}", "  GetUser  "),
+            ("___", "{chars: _}", ""),
+        ];
+
+        for (value, args, expected) in cases {
+            assert_eq!(
+                execute(&Trim, value, args).as_deref(),
+                Ok(expected),
+                "trim of `{}` with {}",
+                value,
+                args
+            );
+        }
+
+        let err = execute(&Trim, "GetUser", "{chars: 1}").unwrap_err();
+        assert!(err.contains("'chars' must be a string"), "{}", err);
+    }
+
+    #[test]
+    fn replace_with_regex() {
+        let cases = [
+            // `$` anchors the pattern, rather than being matched literally
+            ("GetUserHandler", "{pattern: Handler$, with: ''}", "GetUser"),
+            (
+                "HandlerOfHandler",
+                "{pattern: Handler$, with: ''}",
+                "HandlerOf",
+            ),
+            (
+                "get_user_id",
+                "{pattern: '_(\\w)', with: '-$1'}",
+                "get-user-id",
+            ),
+            (
+                "GetUser",
+                "{pattern: '(?P<verb>[A-Z][a-z]+)(?P<noun>\\w+)', with: '${noun}/${verb}'}",
+                "User/Get",
+            ),
+            ("a.b.c", "{pattern: '\\.', with: /}", "a/b/c"),
+            ("a.b.c", "{pattern: '\\.', with: /, count: 1}", "a/b.c"),
+            ("a.b.c", "{pattern: '\\.', with: /, count: 0}", "a/b/c"),
+        ];
+
+        for (value, args, expected) in cases {
+            assert_eq!(
+                execute(&Replace, value, args).as_deref(),
+                Ok(expected),
+                "replace in `{}` with {}",
+                value,
+                args
+            );
+        }
+    }
+
+    #[test]
+    fn replace_literally() {
+        let cases = [
+            (
+                "GetUserHandler",
+                "{pattern: Handler$, with: '', literal: true}",
+                "GetUserHandler",
+            ),
+            ("a.b.c", "{pattern: ., with: $1, literal: true}", "a$1b$1c"),
+            (
+                "a.b.c",
+                "{pattern: ., with: /, literal: true, count: 1}",
+                "a/b.c",
+            ),
+        ];
+
+        for (value, args, expected) in cases {
+            assert_eq!(
+                execute(&Replace, value, args).as_deref(),
+                Ok(expected),
+                "replace in `{}` with {}",
+                value,
+                args
+            );
+        }
+    }
+
+    #[test]
+    fn replace_errors() {
+        let cases = [
+            (
+                "{pattern: '(unclosed', with: ''}",
+                "Invalid pattern `(unclosed`",
+            ),
+            ("{with: ''}", "Missing required argument 'pattern'"),
+            ("{pattern: a}", "Missing required argument 'with'"),
+            (
+                "{pattern: a, with: b, count: -1}",
+                "'count' must not be negative",
+            ),
+            (
+                "{pattern: a, with: b, literal: yes please}",
+                "'literal' must be a boolean",
+            ),
+        ];
+
+        for (args, expected) in cases {
+            let err = execute(&Replace, "GetUser", args).unwrap_err();
+            assert!(err.contains(expected), "replace with {}: {}", args, err);
+        }
+    }
+
+    fn custom_function(
+        script: &str,
+        function: Option<&str>,
+        limits: &ScriptLimitsConfig,
+    ) -> Result<CustomFunction, TransformError> {
+        let step = CustomStep {
+            name: "custom".to_string(),
+            script: script.to_string(),
+            function: function.map(str::to_string),
+        };
+        let engine = script_engine(limits);
+        let ast = engine.compile(&step.script).unwrap();
+        let entry = entry_function(&step, &ast)?;
+        Ok(CustomFunction {
+            engine: Arc::new(engine),
+            ast,
+            entry,
+        })
+    }
+
+    fn exceeded_limit_of(func: &CustomFunction) -> Option<String> {
+        match func.execute(&context("GetUser"), None) {
+            Err(ExecutionError::LimitExceeded { limit }) => Some(limit),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn looping_script_times_out() {
+        let limits = ScriptLimitsConfig {
+            max_operations: 0,
+            timeout_ms: 50,
+            ..Default::default()
+        };
+        let func = custom_function("loop {}", None, &limits).unwrap();
+
+        assert_eq!(exceeded_limit_of(&func).as_deref(), Some("timeout_ms"));
+    }
+
+    #[test]
+    fn limits_set_to_zero_are_disabled() {
+        let scripts = [
+            (
+                "let values = []; for i in 0..10001 { values.push(\"x\"); } \"\" + values.len()",
+                "max_array_size",
+            ),
+            (
+                "let sum = 0; for i in 0..1000000 { sum += i; } \"\" + sum",
+                "max_operations",
+            ),
+        ];
+        let disabled = ScriptLimitsConfig {
+            max_operations: 0,
+            max_call_depth: 0,
+            max_string_size: 0,
+            max_array_size: 0,
+            max_map_size: 0,
+            timeout_ms: 0,
+        };
+
+        for (script, limit) in scripts {
+            let func = custom_function(script, None, &ScriptLimitsConfig::default()).unwrap();
+            assert_eq!(
+                exceeded_limit_of(&func).as_deref(),
+                Some(limit),
+                "{}",
+                script
+            );
+
+            let func = custom_function(script, None, &disabled).unwrap();
+            assert!(
+                func.execute(&context("GetUser"), None).is_ok(),
+                "{} with disabled limits",
+                script
+            );
+        }
+        // Deep recursion overflows the test thread's stack before any call depth limit
+        assert_eq!(max_call_levels(disabled.max_call_depth), usize::MAX);
+    }
+
+    #[test]
+    fn custom_function_calls_entry_function_or_whole_script() {
+        let cases = [
+            (
+                "fn transform(context) { context.matched_string + \"Handler\" }",
+                None,
+                "GetUserHandler",
+            ),
+            (
+                "fn transform(context, args) { context.matched_string + args.suffix }",
+                None,
+                "GetUserDto",
+            ),
+            (
+                "fn transform(context) { \"unused\" } fn to_path(context) { \"/users\" }",
+                Some("to_path"),
+                "/users",
+            ),
+            ("context.matched_string.to_lower()", None, "getuser"),
+        ];
+
+        for (script, function, expected) in cases {
+            let func = custom_function(script, function, &ScriptLimitsConfig::default()).unwrap();
+            let value = execute(&func, "GetUser", "{script: unused, suffix: Dto}");
+            assert_eq!(value.unwrap(), expected, "{}", script);
+        }
+    }
+
+    #[test]
+    fn explicit_entry_function_must_exist() {
+        let limits = ScriptLimitsConfig::default();
+        let err = custom_function("context.matched_string", Some("to_path"), &limits)
+            .err()
+            .unwrap();
+
+        assert!(
+            matches!(err, TransformError::MissingEntryFunction { function, .. } if function == "to_path")
+        );
+    }
+
+    #[test]
+    fn script_args_leave_out_reserved_args() {
+        let args: serde_yaml::Value =
+            serde_yaml::from_str("{script: s, file: f.rhai, function: f, suffix: Dto, count: 2}")
+                .unwrap();
+        let map = script_args(Some(&args)).unwrap();
+
+        let mut keys: Vec<&str> = map.keys().map(|key| key.as_str()).collect();
+        keys.sort();
+        assert_eq!(keys, ["count", "suffix"]);
+        assert_eq!(map["count"].as_int(), Ok(2));
+    }
+
+    #[test]
+    fn regex_cache_is_bounded() {
+        for i in 0..REGEX_CACHE_CAPACITY + 10 {
+            cached_regex(&format!("bounded_{}", i)).unwrap();
+        }
 
-        result
-            .try_cast::<String>()
-            .ok_or_else(|| "Script did not return a string".to_string())
+        assert!(REGEX_CACHE.lock().unwrap().len() <= REGEX_CACHE_CAPACITY);
     }
 }